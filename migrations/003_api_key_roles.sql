@@ -0,0 +1,10 @@
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS role VARCHAR(20) NOT NULL DEFAULT 'user';
+ALTER TABLE api_keys ADD CONSTRAINT api_key_role CHECK (role IN ('user', 'admin'));
+
+-- API keys are looked up by the hex SHA-256 of the bearer token. Keys stored before
+-- that were plaintext; hash them so they keep working. A plaintext key that is itself
+-- 64 lowercase hex characters cannot be told apart from a hash and is left as is, so
+-- such keys have to be re-issued.
+UPDATE api_keys
+SET key = encode(sha256(convert_to(key, 'UTF8')), 'hex')
+WHERE key !~ '^[0-9a-f]{64}$';
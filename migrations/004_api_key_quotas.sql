@@ -0,0 +1,19 @@
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS daily_links_quota BIGINT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS monthly_links_quota BIGINT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS daily_analytics_quota BIGINT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS monthly_analytics_quota BIGINT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS daily_qr_quota BIGINT;
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS monthly_qr_quota BIGINT;
+
+CREATE TABLE IF NOT EXISTS api_key_usage (
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    metric VARCHAR(32) NOT NULL,
+    period VARCHAR(10) NOT NULL,
+    period_start DATE NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (api_key_id, metric, period, period_start),
+    CONSTRAINT usage_period CHECK (period IN ('day', 'month'))
+);
+
+CREATE INDEX idx_api_key_usage_period_start ON api_key_usage(period_start);
@@ -0,0 +1,2 @@
+ALTER TABLE links ADD COLUMN IF NOT EXISTS redirect_type SMALLINT;
+ALTER TABLE links ADD CONSTRAINT link_redirect_type CHECK (redirect_type IN (301, 302, 303, 307, 308));
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS link_revisions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    link_id UUID NOT NULL REFERENCES links(id) ON DELETE CASCADE,
+    old_url TEXT NOT NULL,
+    new_url TEXT NOT NULL,
+    old_expires_at TIMESTAMPTZ,
+    new_expires_at TIMESTAMPTZ,
+    old_redirect_type SMALLINT,
+    new_redirect_type SMALLINT,
+    changed_by_owner_id UUID NOT NULL,
+    changed_by_api_key_id UUID NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Revisions are listed newest first with the id breaking ties between ones recorded at
+-- the same instant.
+CREATE INDEX idx_link_revisions_link_id ON link_revisions(link_id, changed_at DESC, id DESC);
@@ -0,0 +1,11 @@
+ALTER TABLE links ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+CREATE INDEX idx_links_deleted_at ON links(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Clicks outlive their link: purging a trashed link detaches its analytics instead of
+-- deleting them.
+ALTER TABLE link_analytics ALTER COLUMN link_id DROP NOT NULL;
+ALTER TABLE link_analytics DROP CONSTRAINT IF EXISTS link_analytics_link_id_fkey;
+ALTER TABLE link_analytics
+    ADD CONSTRAINT link_analytics_link_id_fkey
+    FOREIGN KEY (link_id) REFERENCES links(id) ON DELETE SET NULL;
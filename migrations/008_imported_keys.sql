@@ -0,0 +1,5 @@
+-- Keys carried over from other shorteners (Bitly back-halves, YOURLS keywords) can be
+-- longer than the ones generated here.
+ALTER TABLE links DROP CONSTRAINT IF EXISTS key_length;
+ALTER TABLE links ALTER COLUMN key TYPE VARCHAR(100);
+ALTER TABLE links ADD CONSTRAINT key_length CHECK (length(key) <= 100);
@@ -0,0 +1,15 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Lowercased host of a destination URL, used by the domain filter on link listings.
+CREATE OR REPLACE FUNCTION link_domain(url TEXT) RETURNS TEXT AS $$
+    SELECT lower(substring(url from '^[A-Za-z][A-Za-z0-9+.-]*://(?:[^@/?#]*@)?([^/:?#]+)'))
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Trigram indexes serve both exact matches and the '%...%' / '%.domain' patterns.
+CREATE INDEX IF NOT EXISTS idx_links_domain_trgm ON links USING GIN (link_domain(original_url) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_links_original_url_trgm ON links USING GIN (original_url gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_links_key_trgm ON links USING GIN (key gin_trgm_ops);
+
+-- Sorted listings break ties by id, so the id is part of each sort index.
+CREATE INDEX IF NOT EXISTS idx_links_owner_created_at_id ON links(owner_id, created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_links_owner_click_count_id ON links(owner_id, click_count DESC, id DESC);
@@ -0,0 +1,4 @@
+-- Cursor pagination seeks on (sort value, id), including across all owners.
+CREATE INDEX IF NOT EXISTS idx_links_created_at_id ON links(created_at DESC, id DESC);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_link_clicked_at_id ON link_analytics(link_id, clicked_at DESC, id DESC);
@@ -0,0 +1,2 @@
+-- Argon2 PHC string; NULL for links anyone may follow.
+ALTER TABLE links ADD COLUMN IF NOT EXISTS password_hash TEXT;
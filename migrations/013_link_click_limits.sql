@@ -0,0 +1,2 @@
+-- Links stop redirecting once click_count reaches max_clicks; NULL means unlimited.
+ALTER TABLE links ADD COLUMN IF NOT EXISTS max_clicks BIGINT CHECK (max_clicks > 0);
@@ -0,0 +1,4 @@
+-- Links with starts_at in the future are scheduled: visitors get a "coming soon"
+-- response, or are sent to prelaunch_url, until that instant.
+ALTER TABLE links ADD COLUMN IF NOT EXISTS starts_at TIMESTAMPTZ;
+ALTER TABLE links ADD COLUMN IF NOT EXISTS prelaunch_url TEXT;
@@ -0,0 +1,9 @@
+-- Where visitors of an expired link are sent instead of getting 410 Gone: the link's
+-- own fallback_url, else its owner's default.
+ALTER TABLE links ADD COLUMN IF NOT EXISTS fallback_url TEXT;
+
+CREATE TABLE IF NOT EXISTS owner_settings (
+    owner_id UUID PRIMARY KEY,
+    fallback_url TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
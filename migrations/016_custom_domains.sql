@@ -0,0 +1,15 @@
+-- Short domains besides the deployment's own. Keys are unique per domain, with links on
+-- the default domain (domain_id NULL) forming one namespace of their own.
+CREATE TABLE IF NOT EXISTS domains (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    hostname VARCHAR(253) NOT NULL UNIQUE,
+    owner_id UUID NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_domains_owner_id ON domains(owner_id);
+
+ALTER TABLE links ADD COLUMN IF NOT EXISTS domain_id UUID REFERENCES domains(id);
+ALTER TABLE links DROP CONSTRAINT IF EXISTS links_key_key;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_links_domain_key
+    ON links ((COALESCE(domain_id, '00000000-0000-0000-0000-000000000000'::uuid)), key);
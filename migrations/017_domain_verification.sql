@@ -0,0 +1,6 @@
+-- Custom domains serve no links until their owner proves control of the hostname by
+-- publishing the verification token in DNS or at a well-known URL. Domains registered
+-- before this migration have to be verified as well.
+ALTER TABLE domains ADD COLUMN IF NOT EXISTS verification_token TEXT NOT NULL DEFAULT md5(random()::text || clock_timestamp()::text);
+ALTER TABLE domains ALTER COLUMN verification_token DROP DEFAULT;
+ALTER TABLE domains ADD COLUMN IF NOT EXISTS verified_at TIMESTAMPTZ;
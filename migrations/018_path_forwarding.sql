@@ -0,0 +1,2 @@
+-- Links that forward the path and query string following their key onto the destination.
+ALTER TABLE links ADD COLUMN IF NOT EXISTS forward_path BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,27 @@
+-- UTM parameters added to destinations at redirect time. Links take them from their own
+-- columns, then their folder's defaults, then their owner's, field by field.
+ALTER TABLE links
+    ADD COLUMN IF NOT EXISTS utm_source TEXT,
+    ADD COLUMN IF NOT EXISTS utm_medium TEXT,
+    ADD COLUMN IF NOT EXISTS utm_campaign TEXT,
+    ADD COLUMN IF NOT EXISTS utm_term TEXT,
+    ADD COLUMN IF NOT EXISTS utm_content TEXT;
+
+ALTER TABLE folders
+    ADD COLUMN IF NOT EXISTS utm_source TEXT,
+    ADD COLUMN IF NOT EXISTS utm_medium TEXT,
+    ADD COLUMN IF NOT EXISTS utm_campaign TEXT,
+    ADD COLUMN IF NOT EXISTS utm_term TEXT,
+    ADD COLUMN IF NOT EXISTS utm_content TEXT;
+
+ALTER TABLE owner_settings
+    ADD COLUMN IF NOT EXISTS utm_source TEXT,
+    ADD COLUMN IF NOT EXISTS utm_medium TEXT,
+    ADD COLUMN IF NOT EXISTS utm_campaign TEXT,
+    ADD COLUMN IF NOT EXISTS utm_term TEXT,
+    ADD COLUMN IF NOT EXISTS utm_content TEXT;
+
+-- The campaign a click was tagged with, as it was at the time of the click.
+ALTER TABLE link_analytics ADD COLUMN IF NOT EXISTS utm_campaign TEXT;
+CREATE INDEX IF NOT EXISTS idx_link_analytics_utm_campaign
+    ON link_analytics(utm_campaign, clicked_at) WHERE utm_campaign IS NOT NULL;
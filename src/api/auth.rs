@@ -0,0 +1,83 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use super::handlers::{AppError, AppState};
+
+/// Resolves the `Authorization: Bearer <key>` header against `api_keys` and stores the
+/// resulting `AuthContext` in the request extensions for the handlers downstream.
+pub async fn require_api_key(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    let token = bearer_token(req.headers())
+        .ok_or_else(|| AppError::Unauthorized("Missing API key".to_string()))?;
+
+    let auth = state
+        .auth_service
+        .authenticate(token)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired API key".to_string()))?;
+
+    req.extensions_mut().insert(auth);
+    Ok(next.run(req).await)
+}
+
+/// Like [`require_api_key`] but lets anonymous requests through, for public routes that
+/// attribute usage to a key when one is presented. A key that is sent but invalid is
+/// still rejected.
+pub async fn optional_api_key(
+    State(state): State<AppState>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    if req.headers().contains_key(header::AUTHORIZATION) {
+        let auth = match bearer_token(req.headers()) {
+            Some(token) => state.auth_service.authenticate(token).await?,
+            None => None,
+        }
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired API key".to_string()))?;
+
+        req.extensions_mut().insert(auth);
+    }
+
+    Ok(next.run(req).await)
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+
+    if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() {
+        Some(token)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers_with(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token(&headers_with("Bearer abc123")), Some("abc123"));
+        assert_eq!(bearer_token(&headers_with("bearer abc123")), Some("abc123"));
+        assert_eq!(bearer_token(&headers_with("Basic abc123")), None);
+        assert_eq!(bearer_token(&headers_with("Bearer ")), None);
+        assert_eq!(bearer_token(&HeaderMap::new()), None);
+    }
+}
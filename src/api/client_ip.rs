@@ -0,0 +1,73 @@
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+
+use crate::config::IpNetwork;
+
+/// Determines the originating client address. Forwarding headers are only honoured when
+/// the direct peer is a trusted proxy; `X-Forwarded-For` is walked from the right so a
+/// client cannot spoof its address by prepending entries.
+pub fn resolve_client_ip(
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+    trusted_proxies: &[IpNetwork],
+) -> Option<IpAddr> {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+
+    let peer = match peer {
+        Some(peer) if is_trusted(peer) => peer,
+        other => return other,
+    };
+
+    if let Some(forwarded) = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
+        let hops: Vec<IpAddr> = forwarded
+            .split(',')
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+
+        if let Some(client) = hops.iter().rev().find(|ip| !is_trusted(**ip)) {
+            return Some(*client);
+        }
+        if let Some(first) = hops.first() {
+            return Some(*first);
+        }
+    }
+
+    headers
+        .get("x-real-ip")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .or(Some(peer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn networks(list: &[&str]) -> Vec<IpNetwork> {
+        list.iter().map(|n| n.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let trusted = networks(&["10.0.0.0/8"]);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("1.1.1.1, 203.0.113.9, 10.0.0.3"),
+        );
+
+        // Untrusted peers cannot influence the result through headers.
+        let peer = "198.51.100.7".parse().ok();
+        assert_eq!(resolve_client_ip(&headers, peer, &trusted), peer);
+
+        // Behind trusted proxies the right-most untrusted hop is the client.
+        let proxy = "10.0.0.2".parse().ok();
+        assert_eq!(
+            resolve_client_ip(&headers, proxy, &trusted),
+            "203.0.113.9".parse().ok()
+        );
+
+        assert_eq!(resolve_client_ip(&HeaderMap::new(), proxy, &trusted), proxy);
+    }
+}
@@ -1,21 +1,59 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Redirect, Response},
-    Json,
+    body::Body,
+    extract::{ConnectInfo, Extension, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Form, Json,
 };
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use futures_util::{future, stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use uuid::Uuid;
 
+use super::client_ip::resolve_client_ip;
+use super::pages::{self, PageTemplates};
+use super::rate_limit::RateLimiter;
 use crate::{
-    domain::{CreateLinkRequest, ErrorResponse, LinkResponse, LinkStats, AnalyticsSummary, LinkAnalytics},
-    services::{LinkService, QrService, AnalyticsService},
+    config::IpNetwork,
+    domain::{
+        is_permanent_redirect, AuthContext, CampaignStats, FolderSettings, UpdateFolderRequest,
+        CreateDomainRequest, Domain, DomainResponse, Link, LinkState, LinkVisit, OwnerSettings,
+        BatchCreateRequest, BatchCreateResponse, BatchItemResult, CreateLinkRequest, ImportReport,
+        ImportRow, ImportRowError, LabelSummary, LinkFilter, LinkSort, Page, LinkStatus, SortField,
+        SortOrder, UpdateLinkRequest, UpdateSettingsRequest, ErrorResponse, LinkResponse, LinkStats,
+        AnalyticsSummary, LinkRevision, TrashedLinkResponse, UsageMetric, UsageReport,
+    },
+    services::{
+        AuthService, DomainService, GeoIp, LinkService, QrService, AnalyticsService, ClickContext,
+        ServiceError, UsageService,
+    },
+    services::destination_template,
+    services::path_forwarding::ForwardedPath,
+    services::link_io::{
+        export_header, export_line, ImportReader, ImportRecord, ImportSource, TransferFormat,
+    },
 };
 
+/// Upper bound on how long browsers may cache a permanent redirect (one day).
+const PERMANENT_REDIRECT_MAX_AGE: i64 = 86_400;
+/// Import rows are created in chunks of this size as the upload streams in.
+const IMPORT_CHUNK_SIZE: usize = 500;
+/// Row errors beyond this many are counted in `failed` but not listed.
+const MAX_IMPORT_ERRORS: usize = 1000;
+
 #[derive(Clone)]
 pub struct AppState {
     pub link_service: Arc<LinkService>,
+    pub domain_service: Arc<DomainService>,
+    pub auth_service: Arc<AuthService>,
+    pub usage_service: Arc<UsageService>,
     pub repository: crate::repository::LinkRepository,
+    pub rate_limiter: RateLimiter,
+    pub trusted_proxies: Arc<Vec<IpNetwork>>,
+    pub pages: Arc<PageTemplates>,
+    pub geoip: Arc<GeoIp>,
 }
 
 pub async fn health_check() -> impl IntoResponse {
@@ -27,88 +65,530 @@ pub async fn health_check() -> impl IntoResponse {
 
 pub async fn create_short_link(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Json(request): Json<CreateLinkRequest>,
 ) -> Result<Json<LinkResponse>, AppError> {
-    let response = state.link_service.create_link(request).await?;
+    let create = state.link_service.create_link(request, auth.owner_id);
+    let response = state
+        .usage_service
+        .metered(&auth, UsageMetric::LinksCreated, create)
+        .await?;
     Ok(Json(response))
 }
 
-pub async fn redirect_to_original(
+pub async fn update_link(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(key): Path<String>,
-    headers: HeaderMap,
-) -> Result<impl IntoResponse, AppError> {
-    let _link = state
+    Query(scope): Query<DomainQuery>,
+    Json(request): Json<UpdateLinkRequest>,
+) -> Result<Json<LinkResponse>, AppError> {
+    let domain_id = scope.domain_id(&state).await?;
+    let response = state
         .link_service
-        .get_link(&key)
+        .update_link(domain_id, &key, request, &auth)
         .await?
-        .ok_or_else(|| AppError::NotFound("Link not found".to_string()))?;
-
-    let referrer = headers
-        .get(header::REFERER)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string());
-
-    let user_agent = headers
-        .get(header::USER_AGENT)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string());
-    
-    let ip_hash = if let Some(x_forwarded_for) = headers.get("x-forwarded-for") {
-        x_forwarded_for
-            .to_str()
-            .ok()
-            .and_then(|s| s.split(',').next())
-            .map(|ip| AnalyticsService::hash_ip(ip.trim()))
-    } else if let Some(x_real_ip) = headers.get("x-real-ip") {
-        x_real_ip
-            .to_str()
-            .ok()
-            .map(|ip| AnalyticsService::hash_ip(ip))
-    } else {
-        None
+        .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))?;
+
+    Ok(Json(response))
+}
+
+pub async fn list_revisions(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(key): Path<String>,
+    Query(query): Query<ListQuery>,
+    Query(scope): Query<DomainQuery>,
+) -> Result<Json<Vec<LinkRevision>>, AppError> {
+    let limit = query.limit.min(100);
+    let domain_id = scope.domain_id(&state).await?;
+    let revisions = state
+        .link_service
+        .list_revisions(domain_id, &key, limit, &auth)
+        .await?
+        .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))?;
+
+    Ok(Json(revisions))
+}
+
+pub async fn restore_revision(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path((key, revision_id)): Path<(String, Uuid)>,
+    Query(scope): Query<DomainQuery>,
+) -> Result<Json<LinkResponse>, AppError> {
+    let domain_id = scope.domain_id(&state).await?;
+    let response = state
+        .link_service
+        .restore_revision(domain_id, &key, revision_id, &auth)
+        .await?;
+
+    Ok(Json(response))
+}
+
+pub async fn create_short_links_batch(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Json(request): Json<BatchCreateRequest>,
+) -> Result<Json<BatchCreateResponse>, AppError> {
+    // Charge the whole batch up front and hand back whatever did not get created.
+    let requested = request.links.len() as i64;
+    let charge = state
+        .usage_service
+        .consume(&auth, UsageMetric::LinksCreated, requested)
+        .await?;
+
+    let results = match state
+        .link_service
+        .create_links(request.links, auth.owner_id, request.all_or_nothing)
+        .await
+    {
+        Ok(results) => results,
+        Err(e) => {
+            state.usage_service.refund(&auth, &charge, requested).await;
+            return Err(e.into());
+        }
+    };
+
+    let results: Vec<BatchItemResult> = results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| match result {
+            Ok(link) => BatchItemResult { index, link: Some(link), error: None },
+            Err(e) => BatchItemResult { index, link: None, error: Some(error_body(&e).1) },
+        })
+        .collect();
+
+    let created = results.iter().filter(|r| r.link.is_some()).count();
+    let failed = results.len() - created;
+    state
+        .usage_service
+        .refund(&auth, &charge, failed as i64)
+        .await;
+
+    Ok(Json(BatchCreateResponse { created, failed, results }))
+}
+
+#[derive(Deserialize)]
+pub struct TransferQuery {
+    format: Option<String>,
+    source: Option<String>,
+    owner_id: Option<Uuid>,
+}
+
+fn transfer_format(name: Option<&str>, content_type: Option<&str>) -> Result<TransferFormat, ServiceError> {
+    match (name, content_type.and_then(TransferFormat::from_content_type)) {
+        (Some(name), _) => TransferFormat::from_name(name)
+            .ok_or_else(|| ServiceError::Validation("format must be csv or ndjson".to_string())),
+        (None, Some(format)) => Ok(format),
+        (None, None) => Err(ServiceError::Validation(
+            "Send text/csv or application/x-ndjson, or pass ?format=csv|ndjson".to_string(),
+        )),
+    }
+}
+
+pub async fn import_links(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<TransferQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<ImportReport>, AppError> {
+    let source = match query.source.as_deref() {
+        Some(name) => ImportSource::from_name(name).ok_or_else(|| {
+            ServiceError::Validation("source must be rustyshort, bitly or yourls".to_string())
+        })?,
+        None => ImportSource::Rustyshort,
+    };
+    // Bitly and YOURLS exports each come in a single, fixed format.
+    let format = match source {
+        ImportSource::Rustyshort => {
+            let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+            transfer_format(query.format.as_deref(), content_type)?
+        }
+        _ => TransferFormat::Csv,
+    };
+
+    let mut reader = ImportReader::new(source, format);
+    let mut report = ImportReport { imported: 0, failed: 0, errors: Vec::new() };
+    let mut pending = Vec::with_capacity(IMPORT_CHUNK_SIZE);
+
+    // Rows are created a chunk at a time as the upload streams in, so neither the body
+    // nor the full set of rows is ever held in memory.
+    let mut body = body.into_data_stream();
+    loop {
+        let records = match body.next().await {
+            Some(chunk) => {
+                let chunk = chunk.map_err(|e| {
+                    ServiceError::Validation(format!("Failed to read request body: {}", e))
+                })?;
+                reader.push(&chunk)?
+            }
+            None => break,
+        };
+        for record in records {
+            queue_import_row(&state, &auth, source, record, &mut pending, &mut report).await;
+        }
+    }
+    for record in reader.finish()? {
+        queue_import_row(&state, &auth, source, record, &mut pending, &mut report).await;
+    }
+    import_chunk(&state, &auth, source, std::mem::take(&mut pending), &mut report).await;
+
+    Ok(Json(report))
+}
+
+async fn queue_import_row(
+    state: &AppState,
+    auth: &AuthContext,
+    source: ImportSource,
+    (line, row): ImportRecord,
+    pending: &mut Vec<(usize, ImportRow)>,
+    report: &mut ImportReport,
+) {
+    match row {
+        Ok(row) => pending.push((line, row)),
+        Err(e) => record_import_failure(report, line, &e),
+    }
+    if pending.len() >= IMPORT_CHUNK_SIZE {
+        import_chunk(state, auth, source, std::mem::take(pending), report).await;
+    }
+}
+
+async fn import_chunk(
+    state: &AppState,
+    auth: &AuthContext,
+    source: ImportSource,
+    rows: Vec<(usize, ImportRow)>,
+    report: &mut ImportReport,
+) {
+    if rows.is_empty() {
+        return;
+    }
+
+    let (lines, rows): (Vec<usize>, Vec<ImportRow>) = rows.into_iter().unzip();
+    let requested = rows.len() as i64;
+
+    let (charge, results) = match state.usage_service.consume(auth, UsageMetric::LinksCreated, requested).await {
+        Ok(charge) => (charge, state.link_service.import_links(rows, auth.owner_id, source).await),
+        Err(e) => {
+            for line in lines {
+                record_import_failure(report, line, &e);
+            }
+            return;
+        }
     };
 
+    match results {
+        Ok(results) => {
+            let mut failed = 0;
+            for (line, result) in lines.into_iter().zip(results) {
+                match result {
+                    Ok(_) => report.imported += 1,
+                    Err(e) => {
+                        failed += 1;
+                        record_import_failure(report, line, &e);
+                    }
+                }
+            }
+            state.usage_service.refund(auth, &charge, failed).await;
+        }
+        Err(e) => {
+            state.usage_service.refund(auth, &charge, requested).await;
+            for line in lines {
+                record_import_failure(report, line, &e);
+            }
+        }
+    }
+}
+
+fn record_import_failure(report: &mut ImportReport, line: usize, err: &ServiceError) {
+    report.failed += 1;
+    if report.errors.len() < MAX_IMPORT_ERRORS {
+        report.errors.push(ImportRowError { line, error: error_body(err).1 });
+    }
+}
+
+pub async fn export_links(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<TransferQuery>,
+) -> Result<Response, AppError> {
+    let format = transfer_format(query.format.as_deref().or(Some("csv")), None)?;
+    let owner_id = list_scope(&auth, query.owner_id)?;
+
+    let header = stream::once(future::ready(Ok(export_header(format))));
+    let lines = state
+        .link_service
+        .export_links(owner_id)
+        .map_ok(move |record| export_line(format, &record))
+        .inspect_err(|e| tracing::error!("Link export aborted: {}", e));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"links.{}\"", format.extension()),
+            ),
+        ],
+        Body::from_stream(header.chain(lines)),
+    )
+        .into_response())
+}
+
+/// Both `/{key}` and `/{key}/{*rest}`; what follows the key is taken from the raw URI.
+#[derive(Deserialize)]
+pub struct RedirectPath {
+    key: String,
+}
+
+pub async fn redirect_to_original(
+    State(state): State<AppState>,
+    Path(RedirectPath { key }): Path<RedirectPath>,
+    uri: Uri,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let domain = host_domain(&state, &headers).await?;
+    let link = match state.link_service.visit_link(domain.map(|d| d.id), &key).await? {
+        LinkVisit::Open(link) => *link,
+        LinkVisit::Fallback(url) => return Ok(fallback_response(&url)),
+    };
+    if let Some(response) = prelaunch_response(&state, &link) {
+        return Ok(response);
+    }
+
+    let forwarded = ForwardedPath::from_request(uri.path(), uri.query());
+    let click = click_context(&state, connect_info, &headers);
+    let utm = state.link_service.utm_for(&link).await?;
+    let destination = state.link_service.destination(&link, &click, &utm, &forwarded)?;
+
+    // Protected links only count a click once the password has been entered.
+    if link.is_password_protected() {
+        let prompt = pages::password_prompt(&key, &forwarded.suffix(), None);
+        return Ok(no_store(prompt.into_response()));
+    }
+
+    if let Some(response) = track_click(&state, &link, click, utm.campaign).await? {
+        return Ok(response);
+    }
+
+    let status = state.link_service.effective_redirect_type(&link);
+    Ok(click_redirect(&link, redirect_response(status, &destination, link.expires_at)))
+}
+
+#[derive(Deserialize)]
+pub struct PasswordForm {
+    password: String,
+}
+
+/// Receives the password prompt. A correct password redirects with `303 See Other`,
+/// which browsers follow with a GET whatever the link's own redirect type.
+pub async fn unlock_link(
+    State(state): State<AppState>,
+    Path(RedirectPath { key }): Path<RedirectPath>,
+    uri: Uri,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    Form(form): Form<PasswordForm>,
+) -> Result<Response, AppError> {
+    let domain = host_domain(&state, &headers).await?;
+    let link = match state.link_service.visit_link(domain.map(|d| d.id), &key).await? {
+        LinkVisit::Open(link) => *link,
+        LinkVisit::Fallback(url) => return Ok(fallback_response(&url)),
+    };
+    if let Some(response) = prelaunch_response(&state, &link) {
+        return Ok(response);
+    }
+
+    let forwarded = ForwardedPath::from_request(uri.path(), uri.query());
+    let click = click_context(&state, connect_info, &headers);
+    let utm = state.link_service.utm_for(&link).await?;
+    let destination = state.link_service.destination(&link, &click, &utm, &forwarded)?;
+
+    if !state.link_service.check_link_password(&link, &form.password).await? {
+        let prompt = pages::password_prompt(
+            &key,
+            &forwarded.suffix(),
+            Some("Incorrect password, please try again."),
+        );
+        return Ok(no_store((StatusCode::UNAUTHORIZED, prompt).into_response()));
+    }
+
+    if let Some(response) = track_click(&state, &link, click, utm.campaign).await? {
+        return Ok(response);
+    }
+
+    let response = redirect_response(StatusCode::SEE_OTHER.as_u16(), &destination, link.expires_at);
+    Ok(click_redirect(&link, response))
+}
+
+/// What visitors of a link that has not gone live yet get: a temporary redirect to its
+/// pre-launch URL, or the "coming soon" page with `Retry-After` pointing at the launch.
+/// `None` once the link is live.
+fn prelaunch_response(state: &AppState, link: &Link) -> Option<Response> {
+    if link.state_at(Utc::now()) != LinkState::Scheduled {
+        return None;
+    }
+    let starts_at = link.starts_at?;
+
+    if let Some(ref prelaunch_url) = link.prelaunch_url {
+        return Some(redirect_response(StatusCode::FOUND.as_u16(), prelaunch_url, None));
+    }
+
+    let retry_after = (starts_at - Utc::now()).num_seconds().max(1);
+    let mut response = no_store(
+        (StatusCode::SERVICE_UNAVAILABLE, state.pages.coming_soon(starts_at)).into_response(),
+    );
+    response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+    Some(response)
+}
+
+/// The custom domain a visitor-facing request was sent to, by its `Host` header.
+async fn host_domain(state: &AppState, headers: &HeaderMap) -> Result<Option<Domain>, AppError> {
+    let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+    Ok(state.domain_service.for_host(host).await?)
+}
+
+/// Where visitors of an expired or used-up link are sent. The redirect is temporary so
+/// that browsers come back once the link is extended, and no click is counted.
+fn fallback_response(url: &str) -> Response {
+    redirect_response(StatusCode::FOUND.as_u16(), url, None)
+}
+
+/// What the request tells about the visitor, with the client IP located by GeoIP.
+fn click_context(
+    state: &AppState,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: &HeaderMap,
+) -> ClickContext {
+    let header_value = |name| headers.get(name).and_then(|v: &HeaderValue| v.to_str().ok());
+
+    let peer = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip());
+    let client_ip = resolve_client_ip(headers, peer, &state.trusted_proxies);
+
+    ClickContext::new(
+        header_value(header::REFERER),
+        header_value(header::USER_AGENT),
+        header_value(header::ACCEPT_LANGUAGE),
+        client_ip.map(|ip| AnalyticsService::hash_ip(&ip.to_string())),
+        client_ip.and_then(|ip| state.geoip.country(ip)),
+    )
+}
+
+/// Counts the click and records its analytics, along with the campaign the destination
+/// was tagged with. Click-limited links are counted before redirecting; once the budget
+/// is used up the visitor gets the link's fallback instead, returned as the response to
+/// send. Everything else happens in the background after the response has been sent.
+async fn track_click(
+    state: &AppState,
+    link: &Link,
+    click: ClickContext,
+    campaign: Option<String>,
+) -> Result<Option<Response>, AppError> {
+    let counted = link.max_clicks.is_some();
+    if counted && !state.link_service.claim_click(link).await? {
+        let url = state.link_service.fallback_url(link, LinkState::Exhausted).await?;
+        return Ok(Some(fallback_response(&url)));
+    }
+
     tokio::spawn({
         let service = state.link_service.clone();
-        let key = key.clone();
-        let link_id = _link.id;
+        let link = link.clone();
         async move {
-            let _ = service.increment_click(&key).await;
-            let _ = service
-                .record_analytics(link_id, referrer, user_agent, ip_hash)
-                .await;
+            if !counted {
+                let _ = service.increment_click(&link).await;
+            }
+            let _ = service.record_analytics(link.id, click, campaign).await;
         }
     });
 
-    Ok(Redirect::permanent(&_link.original_url))
+    Ok(None)
+}
+
+/// A redirect to a templated destination is different on every click, so it is never
+/// cached, whatever the link's redirect type.
+fn click_redirect(link: &Link, response: Response) -> Response {
+    if destination_template::has_placeholders(&link.original_url) {
+        no_store(response)
+    } else {
+        response
+    }
+}
+
+/// Pages served in place of a redirect depend on the visitor's state and must not be cached.
+fn no_store(mut response: Response) -> Response {
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-cache, no-store, must-revalidate"),
+    );
+    response
+}
+
+/// Builds the redirect for a link. Permanent redirects may be cached by browsers, but
+/// never beyond the link's expiry; temporary ones must come back through us on every
+/// visit so repeat clicks are still counted.
+fn redirect_response(status: u16, location: &str, expires_at: Option<DateTime<Utc>>) -> Response {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::FOUND);
+
+    let cache_control = if is_permanent_redirect(status.as_u16()) {
+        let max_age = expires_at
+            .map(|at| (at - Utc::now()).num_seconds().clamp(0, PERMANENT_REDIRECT_MAX_AGE))
+            .unwrap_or(PERMANENT_REDIRECT_MAX_AGE);
+        format!("public, max-age={}", max_age)
+    } else {
+        "private, no-cache, no-store, must-revalidate".to_string()
+    };
+
+    (
+        status,
+        [
+            (header::LOCATION, location.to_string()),
+            (header::CACHE_CONTROL, cache_control),
+        ],
+    )
+        .into_response()
 }
 
 pub async fn get_link_stats(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(key): Path<String>,
+    Query(scope): Query<DomainQuery>,
 ) -> Result<Json<LinkStats>, AppError> {
+    let domain_id = scope.domain_id(&state).await?;
     let stats = state
         .link_service
-        .get_stats(&key)
+        .get_stats(domain_id, &key, &auth)
         .await?
-        .ok_or_else(|| AppError::NotFound("Link not found".to_string()))?;
+        .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))?;
 
     Ok(Json(stats))
 }
 
 pub async fn generate_qr_code(
     State(state): State<AppState>,
+    auth: Option<Extension<AuthContext>>,
     Path(key): Path<String>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let _link = state
-        .link_service
-        .get_link(&key)
-        .await?
-        .ok_or_else(|| AppError::NotFound("Link not found".to_string()))?;
+    let domain = host_domain(&state, &headers).await?;
+    let render = async {
+        // Expired links with a fallback still lead somewhere, so their codes stay useful.
+        state.link_service.visit_link(domain.as_ref().map(|d| d.id), &key).await?;
 
-    let short_url = format!("{}/{}", state.link_service.base_url, key);
-    let qr_data = QrService::generate_qr_code(&short_url)?;
+        let short_url = state.link_service.short_url(domain.as_ref().map(|d| d.hostname.as_str()), &key);
+        Ok(QrService::generate_qr_code(&short_url)?)
+    };
+
+    // Anonymous renders are allowed; only key-authenticated ones count against quotas.
+    let qr_data = match auth {
+        Some(Extension(auth)) => {
+            state.usage_service.metered(&auth, UsageMetric::QrRenders, render).await?
+        }
+        None => render.await?,
+    };
 
     Ok((
         StatusCode::OK,
@@ -119,14 +599,17 @@ pub async fn generate_qr_code(
 
 pub async fn delete_link(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(key): Path<String>,
+    Query(scope): Query<DomainQuery>,
 ) -> Result<StatusCode, AppError> {
-    let deleted = state.link_service.delete_link(&key).await?;
+    let domain_id = scope.domain_id(&state).await?;
+    let deleted = state.link_service.delete_link(domain_id, &key, &auth).await?;
 
     if deleted {
         Ok(StatusCode::NO_CONTENT)
     } else {
-        Err(AppError::NotFound("Link not found".to_string()))
+        Err(ServiceError::NotFound("Link not found".to_string()).into())
     }
 }
 
@@ -136,6 +619,67 @@ pub struct ListQuery {
     limit: i64,
     #[serde(default)]
     offset: i64,
+    #[serde(default)]
+    owner_id: Option<Uuid>,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    folder: Option<String>,
+    #[serde(default)]
+    domain: Option<String>,
+    #[serde(default)]
+    search: Option<String>,
+    #[serde(default)]
+    created_after: Option<DateTime<Utc>>,
+    #[serde(default)]
+    created_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    status: Option<LinkStatus>,
+    #[serde(default)]
+    sort: Option<SortField>,
+    #[serde(default)]
+    order: Option<SortOrder>,
+    #[serde(default)]
+    cursor: Option<String>,
+}
+
+impl ListQuery {
+    fn filter(&self, auth: &AuthContext) -> Result<LinkFilter, AppError> {
+        if let (Some(after), Some(before)) = (self.created_after, self.created_before) {
+            if after >= before {
+                return Err(ServiceError::Validation(
+                    "created_after must be before created_before".to_string(),
+                )
+                .into());
+            }
+        }
+
+        Ok(LinkFilter {
+            owner_id: list_scope(auth, self.owner_id)?,
+            tag: self.tag.as_ref().map(|t| t.trim().to_lowercase()),
+            folder: self.folder.as_ref().map(|f| f.trim().to_string()),
+            domain: self
+                .domain
+                .as_ref()
+                .map(|d| d.trim().trim_end_matches('.').to_lowercase())
+                .filter(|d| !d.is_empty()),
+            search: self.search.as_ref().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+            created_after: self.created_after,
+            created_before: self.created_before,
+            status: self.status,
+        })
+    }
+
+    /// The requested ordering; `None` keeps the listing's natural order.
+    fn sort(&self) -> Option<LinkSort> {
+        if self.sort.is_none() && self.order.is_none() {
+            return None;
+        }
+        Some(LinkSort {
+            field: self.sort.unwrap_or_default(),
+            order: self.order.unwrap_or_default(),
+        })
+    }
 }
 
 fn default_limit() -> i64 {
@@ -144,17 +688,172 @@ fn default_limit() -> i64 {
 
 pub async fn list_links(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    uri: Uri,
     Query(query): Query<ListQuery>,
-) -> Result<Json<Vec<LinkResponse>>, AppError> {
-    let limit = query.limit.min(100);
-    let links = state.link_service.list_links(limit, query.offset).await?;
+) -> Result<Response, AppError> {
+    let limit = query.limit.clamp(1, 100);
+    let filter = query.filter(&auth)?;
+
+    let page = state
+        .link_service
+        .list_links(&filter, query.sort(), query.cursor.as_deref(), limit, query.offset)
+        .await?;
+    Ok(page_response(&state, &uri, page))
+}
+
+pub async fn list_trash(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<TrashedLinkResponse>>, AppError> {
+    let limit = query.limit.clamp(1, 100);
+    let filter = query.filter(&auth)?;
+
+    let links = state.link_service.list_trash(&filter, query.sort(), limit, query.offset).await?;
     Ok(Json(links))
 }
 
+/// Names the custom domain of the link a management request is about; without it the
+/// key is looked up on the default domain.
+#[derive(Deserialize)]
+pub struct DomainQuery {
+    #[serde(default)]
+    domain: Option<String>,
+}
+
+impl DomainQuery {
+    async fn domain_id(&self, state: &AppState) -> Result<Option<Uuid>, AppError> {
+        let domain = state.domain_service.resolve(self.domain.as_deref()).await?;
+        Ok(domain.map(|d| d.id))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ScopeQuery {
+    #[serde(default)]
+    owner_id: Option<Uuid>,
+}
+
+pub async fn list_tags(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<ScopeQuery>,
+) -> Result<Json<Vec<LabelSummary>>, AppError> {
+    let owner_id = list_scope(&auth, query.owner_id)?;
+    Ok(Json(state.link_service.list_tags(owner_id).await?))
+}
+
+pub async fn list_folders(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<ScopeQuery>,
+) -> Result<Json<Vec<LabelSummary>>, AppError> {
+    let owner_id = list_scope(&auth, query.owner_id)?;
+    Ok(Json(state.link_service.list_folders(owner_id).await?))
+}
+
+pub async fn update_folder(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(name): Path<String>,
+    Query(query): Query<ScopeQuery>,
+    Json(request): Json<UpdateFolderRequest>,
+) -> Result<Json<FolderSettings>, AppError> {
+    let owner_id = list_scope(&auth, query.owner_id)?.unwrap_or(auth.owner_id);
+    Ok(Json(state.link_service.update_folder(owner_id, &name, request).await?))
+}
+
+pub async fn list_campaigns(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<CampaignStats>>, AppError> {
+    let days = query.days.min(365);
+    let owner_id = list_scope(&auth, query.owner_id)?;
+    let campaigns = state
+        .usage_service
+        .metered(&auth, UsageMetric::AnalyticsQueries, state.link_service.list_campaigns(owner_id, days))
+        .await?;
+
+    Ok(Json(campaigns))
+}
+
+pub async fn get_settings(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<ScopeQuery>,
+) -> Result<Json<OwnerSettings>, AppError> {
+    let owner_id = list_scope(&auth, query.owner_id)?.unwrap_or(auth.owner_id);
+    Ok(Json(state.link_service.get_settings(owner_id).await?))
+}
+
+pub async fn update_settings(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<ScopeQuery>,
+    Json(request): Json<UpdateSettingsRequest>,
+) -> Result<Json<OwnerSettings>, AppError> {
+    let owner_id = list_scope(&auth, query.owner_id)?.unwrap_or(auth.owner_id);
+    Ok(Json(state.link_service.update_settings(owner_id, request).await?))
+}
+
+pub async fn restore_link(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(key): Path<String>,
+    Query(scope): Query<DomainQuery>,
+) -> Result<Json<LinkResponse>, AppError> {
+    let domain_id = scope.domain_id(&state).await?;
+    let response = state.link_service.restore_link(domain_id, &key, &auth).await?;
+    Ok(Json(response))
+}
+
+pub async fn create_domain(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Json(request): Json<CreateDomainRequest>,
+) -> Result<(StatusCode, Json<DomainResponse>), AppError> {
+    let domain = state.domain_service.create_domain(request, auth.owner_id).await?;
+    Ok((StatusCode::CREATED, Json(domain)))
+}
+
+pub async fn list_domains(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(query): Query<ScopeQuery>,
+) -> Result<Json<Vec<DomainResponse>>, AppError> {
+    let owner_id = list_scope(&auth, query.owner_id)?;
+    Ok(Json(state.domain_service.list_domains(owner_id).await?))
+}
+
+pub async fn verify_domain(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(hostname): Path<String>,
+) -> Result<Json<DomainResponse>, AppError> {
+    Ok(Json(state.domain_service.verify_domain(&hostname, &auth).await?))
+}
+
+/// Admins see every tenant unless they narrow it down; everyone else is pinned to their
+/// own links and may not ask for somebody else's.
+fn list_scope(auth: &AuthContext, requested: Option<Uuid>) -> Result<Option<Uuid>, AppError> {
+    match requested {
+        Some(owner_id) if owner_id != auth.owner_id && !auth.is_admin => {
+            Err(ServiceError::Forbidden("Not allowed to list links of another owner".to_string()).into())
+        }
+        Some(owner_id) => Ok(Some(owner_id)),
+        None if auth.is_admin => Ok(None),
+        None => Ok(Some(auth.owner_id)),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct AnalyticsQuery {
     #[serde(default = "default_days")]
     days: i32,
+    #[serde(default)]
+    owner_id: Option<Uuid>,
 }
 
 fn default_days() -> i32 {
@@ -163,74 +862,236 @@ fn default_days() -> i32 {
 
 pub async fn get_analytics_summary(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(key): Path<String>,
     Query(query): Query<AnalyticsQuery>,
+    Query(scope): Query<DomainQuery>,
 ) -> Result<Json<AnalyticsSummary>, AppError> {
     let days = query.days.min(365);
+    let domain_id = scope.domain_id(&state).await?;
     let summary = state
-        .link_service
-        .get_analytics_summary(&key, days)
-        .await?
-        .ok_or_else(|| AppError::NotFound("Link not found".to_string()))?;
+        .usage_service
+        .metered(&auth, UsageMetric::AnalyticsQueries, async {
+            state
+                .link_service
+                .get_analytics_summary(domain_id, &key, days, &auth)
+                .await?
+                .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))
+        })
+        .await?;
+
+    Ok(Json(summary))
+}
+
+pub async fn get_tag_analytics(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(tag): Path<String>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<AnalyticsSummary>, AppError> {
+    let days = query.days.min(365);
+    let owner_id = list_scope(&auth, query.owner_id)?;
+    let summary = state
+        .usage_service
+        .metered(&auth, UsageMetric::AnalyticsQueries, async {
+            state
+                .link_service
+                .get_tag_analytics(&tag, owner_id, days)
+                .await?
+                .ok_or_else(|| ServiceError::NotFound("Tag not found".to_string()))
+        })
+        .await?;
 
     Ok(Json(summary))
 }
 
+#[derive(Deserialize)]
+pub struct DetailedAnalyticsQuery {
+    #[serde(default = "default_limit")]
+    limit: i64,
+    #[serde(default)]
+    cursor: Option<String>,
+}
+
 pub async fn get_detailed_analytics(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(key): Path<String>,
-    Query(query): Query<ListQuery>,
-) -> Result<Json<Vec<LinkAnalytics>>, AppError> {
-    let limit = query.limit.min(1000);
-    
-    if !state.link_service.get_link(&key).await?.is_some() {
-        return Err(AppError::NotFound("Link not found".to_string()));
+    uri: Uri,
+    Query(query): Query<DetailedAnalyticsQuery>,
+    Query(scope): Query<DomainQuery>,
+) -> Result<Response, AppError> {
+    let limit = query.limit.clamp(1, 1000);
+    let domain_id = scope.domain_id(&state).await?;
+
+    let page = state
+        .usage_service
+        .metered(&auth, UsageMetric::AnalyticsQueries, async {
+            state
+                .link_service
+                .get_detailed_analytics(domain_id, &key, query.cursor.as_deref(), limit, &auth)
+                .await?
+                .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))
+        })
+        .await?;
+    Ok(page_response(&state, &uri, page))
+}
+
+/// Serves a page as JSON, pointing at the next one with a `Link: <...>; rel="next"`
+/// header that repeats the request's parameters with the new cursor.
+fn page_response<T: Serialize>(state: &AppState, uri: &Uri, page: Page<T>) -> Response {
+    let next = page.next_cursor.as_deref().map(|cursor| {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        for (name, value) in url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes()) {
+            if name != "cursor" && name != "offset" {
+                query.append_pair(&name, &value);
+            }
+        }
+        query.append_pair("cursor", cursor);
+        format!("<{}{}?{}>; rel=\"next\"", state.link_service.base_url, uri.path(), query.finish())
+    });
+
+    let mut response = Json(page).into_response();
+    if let Some(value) = next.and_then(|link| HeaderValue::from_str(&link).ok()) {
+        response.headers_mut().insert(header::LINK, value);
     }
-    
-    let analytics = state.repository.get_analytics(&key, limit).await?;
-    Ok(Json(analytics))
+    response
+}
+
+pub async fn get_usage(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+) -> Result<Json<UsageReport>, AppError> {
+    let report = state.usage_service.report(&auth).await?;
+    Ok(Json(report))
 }
 
 pub enum AppError {
-    Internal(anyhow::Error),
-    NotFound(String),
+    Service(ServiceError),
+    Unauthorized(String),
+}
+
+impl From<ServiceError> for AppError {
+    fn from(err: ServiceError) -> Self {
+        AppError::Service(err)
+    }
 }
 
 impl From<anyhow::Error> for AppError {
     fn from(err: anyhow::Error) -> Self {
-        AppError::Internal(err)
+        AppError::Service(ServiceError::Internal(err))
     }
 }
 
 impl From<sqlx::Error> for AppError {
     fn from(err: sqlx::Error) -> Self {
-        AppError::Internal(err.into())
+        AppError::Service(err.into())
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::Internal(err) => {
-                tracing::error!("Internal error: {:?}", err);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    ErrorResponse {
-                        error: "Internal server error".to_string(),
-                        details: None,
-                    },
+        let err = match self {
+            AppError::Service(err) => err,
+            AppError::Unauthorized(msg) => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Bearer")],
+                    Json(ErrorResponse {
+                        error: "Unauthorized".to_string(),
+                        code: "unauthorized".to_string(),
+                        details: Some(msg),
+                    }),
                 )
+                    .into_response();
             }
-            AppError::NotFound(msg) => (
-                StatusCode::NOT_FOUND,
-                ErrorResponse {
-                    error: msg,
-                    details: None,
-                },
-            ),
         };
 
-        (status, Json(error_message)).into_response()
+        let (status, body) = error_body(&err);
+
+        let mut response = (status, Json(body.clone())).into_response();
+        // Lets visitor-facing routes render the error as a page instead.
+        response.extensions_mut().insert(body);
+        if let ServiceError::RateLimited { retry_after_secs: Some(secs), .. } = err {
+            response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+        }
+        response
     }
 }
 
+/// The status and JSON body a service error is reported with, both for whole responses
+/// and for individual items of batch operations.
+fn error_body(err: &ServiceError) -> (StatusCode, ErrorResponse) {
+    let status = match err {
+        ServiceError::Validation(_) => StatusCode::BAD_REQUEST,
+        ServiceError::Conflict(_) => StatusCode::CONFLICT,
+        ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+        ServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+        ServiceError::Gone(_) => StatusCode::GONE,
+        ServiceError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        ServiceError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        ServiceError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    // Internal failures are logged but never echoed back to the client.
+    let details = match err {
+        ServiceError::Internal(inner) => {
+            tracing::error!("Internal error: {:?}", inner);
+            None
+        }
+        other => Some(other.to_string()),
+    };
+
+    let body = ErrorResponse {
+        error: status.canonical_reason().unwrap_or("Error").to_string(),
+        code: err.code().to_string(),
+        details,
+    };
+
+    (status, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_error_status_codes() {
+        let cases = [
+            (ServiceError::Validation("bad".into()), StatusCode::BAD_REQUEST),
+            (ServiceError::Conflict("taken".into()), StatusCode::CONFLICT),
+            (ServiceError::NotFound("missing".into()), StatusCode::NOT_FOUND),
+            (ServiceError::Forbidden("nope".into()), StatusCode::FORBIDDEN),
+            (ServiceError::Gone("deleted".into()), StatusCode::GONE),
+            (
+                ServiceError::RateLimited { message: "slow down".into(), retry_after_secs: Some(3) },
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            (ServiceError::Unavailable("db".into()), StatusCode::SERVICE_UNAVAILABLE),
+            (ServiceError::Internal(anyhow::anyhow!("boom")), StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+
+        for (err, expected) in cases {
+            assert_eq!(AppError::from(err).into_response().status(), expected);
+        }
+    }
+
+    #[test]
+    fn test_redirect_response_cache_headers() {
+        let temporary = redirect_response(302, "https://example.com", None);
+        assert_eq!(temporary.status(), StatusCode::FOUND);
+        assert_eq!(temporary.headers()[header::LOCATION], "https://example.com");
+        assert_eq!(
+            temporary.headers()[header::CACHE_CONTROL],
+            "private, no-cache, no-store, must-revalidate"
+        );
+
+        let permanent = redirect_response(301, "https://example.com", None);
+        assert_eq!(permanent.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(permanent.headers()[header::CACHE_CONTROL], "public, max-age=86400");
+
+        let expired_at = Utc::now() - chrono::Duration::seconds(5);
+        let expiring = redirect_response(308, "https://example.com", Some(expired_at));
+        assert_eq!(expiring.headers()[header::CACHE_CONTROL], "public, max-age=0");
+    }
+}
@@ -1,6 +1,9 @@
+pub mod auth;
+pub mod client_ip;
 pub mod handlers;
+pub mod pages;
+pub mod rate_limit;
 pub mod routes;
 
 pub use handlers::AppState;
 pub use routes::create_router;
-
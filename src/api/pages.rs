@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{Html, Response},
+};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::domain::ErrorResponse;
+
+/// Errors visitors of short links may get a branded page for, each with its heading.
+const ERROR_PAGES: [(StatusCode, &str); 3] = [
+    (StatusCode::NOT_FOUND, "Link not found"),
+    (StatusCode::GONE, "Link no longer available"),
+    (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
+];
+
+/// Pages a deployment may replace with its own HTML, loaded once at startup.
+#[derive(Debug, Clone, Default)]
+pub struct PageTemplates {
+    /// Shown for scheduled links; `{{starts_at}}` is replaced with the launch time.
+    coming_soon: Option<String>,
+    /// Error pages by status code. `{{status}}`, `{{title}}` and `{{message}}` are
+    /// filled in.
+    errors: HashMap<u16, String>,
+}
+
+impl PageTemplates {
+    /// Reads the coming soon page and whichever of `404.html`, `410.html` and `429.html`
+    /// exist in `error_pages`. Pages not supplied fall back to the built-in ones.
+    pub fn load(coming_soon: Option<&Path>, error_pages: Option<&Path>) -> Result<Self> {
+        let coming_soon = coming_soon
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read coming soon page {}", path.display()))
+            })
+            .transpose()?;
+
+        let mut errors = HashMap::new();
+        if let Some(dir) = error_pages {
+            if !dir.is_dir() {
+                anyhow::bail!("Error page directory {} does not exist", dir.display());
+            }
+            for (status, _) in ERROR_PAGES {
+                let path = dir.join(format!("{}.html", status.as_u16()));
+                if path.exists() {
+                    let page = std::fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read error page {}", path.display()))?;
+                    errors.insert(status.as_u16(), page);
+                }
+            }
+        }
+
+        Ok(Self { coming_soon, errors })
+    }
+
+    /// The page shown to visitors instead of an error's JSON body, for the statuses that
+    /// have one.
+    pub fn error_page(&self, status: StatusCode, message: Option<&str>) -> Option<Html<String>> {
+        let (_, title) = ERROR_PAGES.iter().find(|(s, _)| *s == status)?;
+        let message = escape_html(message.unwrap_or(title));
+
+        if let Some(template) = self.errors.get(&status.as_u16()) {
+            return Some(Html(
+                template
+                    .replace("{{status}}", status.as_str())
+                    .replace("{{title}}", title)
+                    .replace("{{message}}", &message),
+            ));
+        }
+
+        Some(Html(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; text-align: center; margin-top: 15vh; }}
+.status {{ color: #888; font-size: 3rem; margin: 0; }}
+</style>
+</head>
+<body>
+<p class="status">{status}</p>
+<h1>{title}</h1>
+<p>{message}</p>
+</body>
+</html>
+"#,
+            status = status.as_str(),
+            title = title,
+            message = message,
+        )))
+    }
+
+    /// The page visitors of a link that is not live yet get.
+    pub fn coming_soon(&self, starts_at: DateTime<Utc>) -> Html<String> {
+        let starts_at = starts_at.to_rfc3339();
+        match self.coming_soon {
+            Some(ref template) => Html(template.replace("{{starts_at}}", &escape_html(&starts_at))),
+            None => Html(format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>Coming soon</title>
+<style>
+body {{ font-family: system-ui, sans-serif; text-align: center; margin-top: 15vh; }}
+</style>
+</head>
+<body>
+<h1>Coming soon</h1>
+<p>This link goes live at <time datetime="{starts_at}">{starts_at}</time>.</p>
+</body>
+</html>
+"#,
+                starts_at = escape_html(&starts_at),
+            )),
+        }
+    }
+}
+
+/// The form a visitor fills in to follow a password-protected link. It posts back to the
+/// short URL itself, with any `forwarded` path and query string; `error` is shown after
+/// a wrong guess.
+pub fn password_prompt(key: &str, forwarded: &str, error: Option<&str>) -> Html<String> {
+    let error = error
+        .map(|message| format!(r#"<p class="error">{}</p>"#, escape_html(message)))
+        .unwrap_or_default();
+
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>Password required</title>
+<style>
+body {{ font-family: system-ui, sans-serif; display: flex; justify-content: center; margin-top: 15vh; }}
+form {{ display: flex; flex-direction: column; gap: 0.75rem; width: 18rem; }}
+.error {{ color: #b00020; margin: 0; }}
+</style>
+</head>
+<body>
+<form method="post" action="/{key}{forwarded}">
+<h1>Password required</h1>
+<label for="password">This link is protected. Enter its password to continue.</label>
+{error}
+<input id="password" name="password" type="password" autocomplete="current-password" required autofocus>
+<button type="submit">Continue</button>
+</form>
+</body>
+</html>
+"#,
+        key = escape_html(&url::form_urlencoded::byte_serialize(key.as_bytes()).collect::<String>()),
+        forwarded = escape_html(forwarded),
+        error = error,
+    ))
+}
+
+/// Swaps the JSON error body of a visitor-facing route for a branded HTML page when the
+/// client prefers HTML, as browsers do. Status and headers such as `Retry-After` are
+/// kept; API clients still get the `ErrorResponse`.
+pub async fn html_errors(
+    State(pages): State<Arc<PageTemplates>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let wants_html = prefers_html(req.headers());
+    let mut response = next.run(req).await;
+
+    let Some(error) = response.extensions().get::<ErrorResponse>() else {
+        return response;
+    };
+    let page = if wants_html {
+        pages.error_page(response.status(), error.details.as_deref())
+    } else {
+        None
+    };
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("accept"));
+
+    let Some(Html(page)) = page else {
+        return response;
+    };
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    Response::from_parts(parts, Body::from(page))
+}
+
+/// Whether the `Accept` header ranks HTML above JSON. Clients that accept anything, or
+/// send no `Accept` at all, get JSON.
+fn prefers_html(headers: &HeaderMap) -> bool {
+    let accept = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let html = media_quality(&accept, "text", "html");
+    html > 0.0 && html > media_quality(&accept, "application", "json")
+}
+
+/// The quality the most specific matching range in `accept` gives a media type.
+fn media_quality(accept: &str, kind: &str, subtype: &str) -> f32 {
+    let mut best: Option<(u8, f32)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';').map(str::trim);
+        let Some((range_kind, range_subtype)) = params.next().and_then(|m| m.split_once('/')) else {
+            continue;
+        };
+
+        let specificity = match (range_kind, range_subtype) {
+            (k, s) if k.eq_ignore_ascii_case(kind) && s.eq_ignore_ascii_case(subtype) => 2,
+            (k, "*") if k.eq_ignore_ascii_case(kind) => 1,
+            ("*", "*") => 0,
+            _ => continue,
+        };
+        let quality = params
+            .filter_map(|p| p.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .and_then(|(_, q)| q.trim().parse().ok())
+            .unwrap_or(1.0);
+
+        if best.is_none_or(|(s, _)| specificity > s) {
+            best = Some((specificity, quality));
+        }
+    }
+
+    best.map_or(0.0, |(_, quality)| quality)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_prompt_escapes_input() {
+        let Html(page) = password_prompt("a\"b", "/x?a=1&b=2", Some("<script>"));
+        assert!(page.contains(r#"action="/a%22b/x?a=1&amp;b=2""#));
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(!page.contains("<script>"));
+    }
+
+    #[test]
+    fn test_coming_soon_template_gets_launch_time() {
+        let starts_at = DateTime::parse_from_rfc3339("2030-01-01T09:00:00Z").unwrap().to_utc();
+        let templates = PageTemplates {
+            coming_soon: Some("<p>Live from {{starts_at}}</p>".to_string()),
+            ..Default::default()
+        };
+        let Html(page) = templates.coming_soon(starts_at);
+        assert_eq!(page, "<p>Live from 2030-01-01T09:00:00+00:00</p>");
+
+        let Html(page) = PageTemplates::default().coming_soon(starts_at);
+        assert!(page.contains("2030-01-01T09:00:00+00:00"));
+    }
+
+    #[test]
+    fn test_error_pages_use_templates_and_escape_messages() {
+        let templates = PageTemplates {
+            errors: HashMap::from([(410, "<h1>{{status}} {{title}}</h1><p>{{message}}</p>".to_string())]),
+            ..Default::default()
+        };
+
+        let Html(page) = templates.error_page(StatusCode::GONE, Some("<b>expired</b>")).unwrap();
+        assert_eq!(page, "<h1>410 Link no longer available</h1><p>&lt;b&gt;expired&lt;/b&gt;</p>");
+
+        let Html(page) = templates.error_page(StatusCode::NOT_FOUND, None).unwrap();
+        assert!(page.contains("<h1>Link not found</h1>"));
+        assert!(templates.error_page(StatusCode::BAD_REQUEST, None).is_none());
+    }
+
+    #[test]
+    fn test_prefers_html_only_when_ranked_above_json() {
+        let prefers = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+            prefers_html(&headers)
+        };
+
+        assert!(prefers("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"));
+        assert!(prefers("text/*"));
+        assert!(!prefers("*/*"));
+        assert!(!prefers("application/json"));
+        assert!(!prefers("application/json, text/html;q=0.5"));
+        assert!(!prefers("text/html;q=0"));
+        assert!(!prefers_html(&HeaderMap::new()));
+    }
+}
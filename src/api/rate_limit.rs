@@ -0,0 +1,368 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use moka::future::Cache;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::client_ip::resolve_client_ip;
+use super::handlers::AppError;
+use crate::{config::IpNetwork, domain::AuthContext, services::ServiceError};
+
+/// Route groups are throttled independently so a burst of redirects cannot starve the
+/// management API and vice versa.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Redirect,
+    Api,
+    Qr,
+}
+
+impl RouteGroup {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Redirect => "redirect",
+            RouteGroup::Api => "api",
+            RouteGroup::Qr => "qr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BucketConfig {
+    pub per_second: u64,
+    pub burst: u32,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[derive(Debug)]
+enum Decision {
+    Allowed { remaining: u64, reset_secs: u64 },
+    Limited { retry_after_secs: u64 },
+}
+
+impl TokenBucket {
+    fn full(config: BucketConfig) -> Self {
+        Self {
+            tokens: config.burst as f64,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn take(&mut self, config: BucketConfig, now: Instant) -> Decision {
+        let rate = config.per_second as f64;
+        let capacity = config.burst as f64;
+
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Decision::Allowed {
+                remaining: self.tokens.floor() as u64,
+                reset_secs: ((capacity - self.tokens) / rate).ceil() as u64,
+            }
+        } else {
+            Decision::Limited {
+                retry_after_secs: ((1.0 - self.tokens) / rate).ceil().max(1.0) as u64,
+            }
+        }
+    }
+
+    /// Like `take`, but leaves the token in the bucket.
+    fn peek(&mut self, config: BucketConfig, now: Instant) -> Decision {
+        let decision = self.take(config, now);
+        if matches!(decision, Decision::Allowed { .. }) {
+            self.tokens += 1.0;
+        }
+        decision
+    }
+}
+
+/// In-process token-bucket limiter. Buckets are keyed by route group and either the
+/// authenticated API key or the client IP, and are evicted once idle.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Cache<(RouteGroup, String), Arc<Mutex<TokenBucket>>>,
+    redirect: BucketConfig,
+    api: BucketConfig,
+    qr: BucketConfig,
+    trusted_proxies: Arc<Vec<IpNetwork>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        redirect: BucketConfig,
+        api: BucketConfig,
+        qr: BucketConfig,
+        trusted_proxies: Arc<Vec<IpNetwork>>,
+    ) -> Self {
+        let buckets = Cache::builder()
+            .max_capacity(100_000)
+            .time_to_idle(Duration::from_secs(600))
+            .build();
+
+        Self {
+            buckets,
+            redirect,
+            api,
+            qr,
+            trusted_proxies,
+        }
+    }
+
+    fn config_for(&self, group: RouteGroup) -> BucketConfig {
+        match group {
+            RouteGroup::Redirect => self.redirect,
+            RouteGroup::Api => self.api,
+            RouteGroup::Qr => self.qr,
+        }
+    }
+
+    async fn bucket(&self, group: RouteGroup, client: String) -> Arc<Mutex<TokenBucket>> {
+        let config = self.config_for(group);
+        self.buckets
+            .get_with((group, client), async move {
+                Arc::new(Mutex::new(TokenBucket::full(config)))
+            })
+            .await
+    }
+
+    async fn check(&self, group: RouteGroup, client: String) -> Decision {
+        let bucket = self.bucket(group, client).await;
+        let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.take(self.config_for(group), Instant::now())
+    }
+
+    async fn peek(&self, group: RouteGroup, client: String) -> Decision {
+        let bucket = self.bucket(group, client).await;
+        let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.peek(self.config_for(group), Instant::now())
+    }
+
+    fn client_ip(&self, req: &Request<Body>) -> String {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        match resolve_client_ip(req.headers(), peer, &self.trusted_proxies) {
+            Some(ip) => format!("ip:{}", ip),
+            None => "ip:unknown".to_string(),
+        }
+    }
+}
+
+pub async fn rate_limit(
+    State((limiter, group)): State<(RateLimiter, RouteGroup)>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let config = limiter.config_for(group);
+    if config.per_second == 0 || config.burst == 0 {
+        return next.run(req).await;
+    }
+
+    let client = match req.extensions().get::<AuthContext>() {
+        Some(auth) => format!("key:{}", auth.api_key_id),
+        None => limiter.client_ip(&req),
+    };
+
+    match limiter.check(group, client).await {
+        Decision::Allowed {
+            remaining,
+            reset_secs,
+        } => {
+            let mut response = next.run(req).await;
+            set_rate_limit_headers(response.headers_mut(), config, remaining, reset_secs);
+            response
+        }
+        Decision::Limited { retry_after_secs } => limited_response(group, config, retry_after_secs),
+    }
+}
+
+/// Throttles requests that fail authentication by client IP. Layered outside the API key
+/// check, which `rate_limit` runs behind so that keys get buckets of their own; without
+/// this, requests with a missing or wrong key would never meet a bucket and keys could
+/// be guessed without limit. Failures are counted in buckets of their own, so requests
+/// that authenticate are never charged here and share nothing with `rate_limit`.
+pub async fn rate_limit_auth_failures(
+    State((limiter, group)): State<(RateLimiter, RouteGroup)>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let config = limiter.config_for(group);
+    if config.per_second == 0 || config.burst == 0 {
+        return next.run(req).await;
+    }
+
+    let client = format!("authfail:{}", limiter.client_ip(&req));
+    if let Decision::Limited { retry_after_secs } = limiter.peek(group, client.clone()).await {
+        return limited_response(group, config, retry_after_secs);
+    }
+
+    let response = next.run(req).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        limiter.check(group, client).await;
+    }
+    response
+}
+
+fn limited_response(group: RouteGroup, config: BucketConfig, retry_after_secs: u64) -> Response {
+    metrics::counter!("rate_limited_requests_total", "group" => group.as_str()).increment(1);
+
+    let mut response = AppError::from(ServiceError::RateLimited {
+        message: "Too many requests, please retry later".to_string(),
+        retry_after_secs: Some(retry_after_secs),
+    })
+    .into_response();
+    set_rate_limit_headers(response.headers_mut(), config, 0, retry_after_secs);
+    response
+}
+
+fn set_rate_limit_headers(
+    headers: &mut HeaderMap,
+    config: BucketConfig,
+    remaining: u64,
+    reset_secs: u64,
+) {
+    headers.insert("ratelimit-limit", HeaderValue::from(config.burst));
+    headers.insert("ratelimit-remaining", HeaderValue::from(remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(reset_secs));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_limits_and_refills() {
+        let config = BucketConfig {
+            per_second: 2,
+            burst: 3,
+        };
+        let mut bucket = TokenBucket::full(config);
+        let start = bucket.updated_at;
+
+        for _ in 0..3 {
+            assert!(matches!(
+                bucket.take(config, start),
+                Decision::Allowed { .. }
+            ));
+        }
+        assert!(matches!(
+            bucket.take(config, start),
+            Decision::Limited {
+                retry_after_secs: 1
+            }
+        ));
+
+        let later = start + Duration::from_millis(500);
+        assert!(matches!(
+            bucket.peek(config, later),
+            Decision::Allowed { .. }
+        ));
+        assert!(matches!(
+            bucket.take(config, later),
+            Decision::Allowed { remaining: 0, .. }
+        ));
+        assert!(matches!(
+            bucket.peek(config, later),
+            Decision::Limited { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_auth_failures_are_limited_by_client_ip() {
+        use axum::{middleware::from_fn_with_state, routing::get, Router};
+        use tower::ServiceExt;
+
+        let bucket = BucketConfig { per_second: 1, burst: 2 };
+        let limiter = RateLimiter::new(bucket, bucket, bucket, Arc::new(Vec::new()));
+        let app = Router::new()
+            .route("/allowed", get(|| async { StatusCode::OK }))
+            .route("/denied", get(|| async { StatusCode::UNAUTHORIZED }))
+            .route_layer(from_fn_with_state((limiter, RouteGroup::Api), rate_limit_auth_failures));
+        let status = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = Request::get(uri).body(Body::empty()).unwrap();
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+
+        for _ in 0..5 {
+            assert_eq!(status("/allowed").await, StatusCode::OK);
+        }
+        assert_eq!(status("/denied").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/denied").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/denied").await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status("/allowed").await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_requests_do_not_use_the_ip_bucket() {
+        use axum::{
+            middleware::{from_fn, from_fn_with_state},
+            routing::get,
+            Router,
+        };
+        use futures_util::future::join_all;
+        use tower::ServiceExt;
+
+        let bucket = BucketConfig { per_second: 1, burst: 2 };
+        let limiter = RateLimiter::new(bucket, bucket, bucket, Arc::new(Vec::new()));
+        let fake_auth = |mut req: Request<Body>, next: Next| async move {
+            if req.headers().contains_key("authorization") {
+                req.extensions_mut().insert(AuthContext {
+                    api_key_id: uuid::Uuid::new_v4(),
+                    owner_id: uuid::Uuid::new_v4(),
+                    is_admin: false,
+                    quotas: Default::default(),
+                });
+            }
+            next.run(req).await
+        };
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    StatusCode::OK
+                }),
+            )
+            .route_layer(from_fn_with_state((limiter.clone(), RouteGroup::Api), rate_limit))
+            .route_layer(from_fn(fake_auth))
+            .route_layer(from_fn_with_state((limiter, RouteGroup::Api), rate_limit_auth_failures));
+
+        // Keys have buckets of their own, however many share the client's address.
+        let authenticated = (0..5).map(|_| {
+            let request = Request::get("/")
+                .header("authorization", "Bearer key")
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request)
+        });
+        for response in join_all(authenticated).await {
+            assert_eq!(response.unwrap().status(), StatusCode::OK);
+        }
+
+        // Anonymous requests are charged once, by `rate_limit` alone.
+        for remaining in ["1", "0"] {
+            let request = Request::get("/").body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["ratelimit-remaining"], remaining);
+        }
+    }
+}
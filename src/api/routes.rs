@@ -1,24 +1,78 @@
 use axum::{
-    routing::{delete, get, post},
+    middleware,
+    routing::{delete, get, patch, post},
     Router,
 };
 
+use super::auth::{optional_api_key, require_api_key};
+use super::pages::html_errors;
+use super::rate_limit::{rate_limit, rate_limit_auth_failures, RouteGroup};
 use super::handlers::{
-    create_short_link, delete_link, generate_qr_code, get_link_stats, health_check, list_links,
-    redirect_to_original, get_analytics_summary, get_detailed_analytics, AppState,
+    create_domain, create_short_link, create_short_links_batch, delete_link, export_links, generate_qr_code,
+    get_link_stats, get_settings, get_tag_analytics, get_usage, health_check, import_links, list_domains, list_folders,
+    list_links, list_revisions, list_tags, list_trash, redirect_to_original, restore_link,
+    restore_revision, unlock_link, update_folder, update_link, update_settings, verify_domain, list_campaigns, get_analytics_summary, get_detailed_analytics, AppState,
 };
-
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        .route("/health", get(health_check))
+    let api = Router::new()
         .route("/api/v1/links", post(create_short_link))
         .route("/api/v1/links", get(list_links))
+        .route("/api/v1/links/batch", post(create_short_links_batch))
+        .route("/api/v1/links/import", post(import_links))
+        .route("/api/v1/links/export", get(export_links))
         .route("/api/v1/links/{key}/stats", get(get_link_stats))
         .route("/api/v1/links/{key}/analytics", get(get_analytics_summary))
         .route("/api/v1/links/{key}/analytics/detailed", get(get_detailed_analytics))
-        .route("/api/v1/links/{key}", delete(delete_link))
+        .route("/api/v1/links/{key}", delete(delete_link).patch(update_link))
+        .route("/api/v1/links/{key}/restore", post(restore_link))
+        .route("/api/v1/links/{key}/revisions", get(list_revisions))
+        .route("/api/v1/links/{key}/revisions/{id}/restore", post(restore_revision))
+        .route("/api/v1/trash", get(list_trash))
+        .route("/api/v1/tags", get(list_tags))
+        .route("/api/v1/tags/{tag}/analytics", get(get_tag_analytics))
+        .route("/api/v1/folders", get(list_folders))
+        .route("/api/v1/folders/{name}", patch(update_folder))
+        .route("/api/v1/campaigns", get(list_campaigns))
+        .route("/api/v1/usage", get(get_usage))
+        .route("/api/v1/settings", get(get_settings).patch(update_settings))
+        .route("/api/v1/domains", get(list_domains).post(create_domain))
+        .route("/api/v1/domains/{hostname}/verify", post(verify_domain))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), RouteGroup::Api),
+            rate_limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_api_key))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), RouteGroup::Api),
+            rate_limit_auth_failures,
+        ));
+
+    let qr = Router::new()
         .route("/qr/{key}", get(generate_qr_code))
-        .route("/{key}", get(redirect_to_original))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), RouteGroup::Qr),
+            rate_limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.clone(), optional_api_key))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), RouteGroup::Qr),
+            rate_limit_auth_failures,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.pages.clone(), html_errors));
+
+    let redirect = Router::new()
+        .route("/{key}", get(redirect_to_original).post(unlock_link))
+        .route("/{key}/{*rest}", get(redirect_to_original).post(unlock_link))
+        .route_layer(middleware::from_fn_with_state(
+            (state.rate_limiter.clone(), RouteGroup::Redirect),
+            rate_limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(state.pages.clone(), html_errors));
+
+    Router::new()
+        .route("/health", get(health_check))
+        .merge(api)
+        .merge(qr)
+        .merge(redirect)
         .with_state(state)
 }
-
@@ -1,11 +1,15 @@
 use moka::future::Cache;
 use std::sync::Arc;
 use std::time::Duration;
+use uuid::Uuid;
 use crate::domain::Link;
 
+/// Links by domain and key; `None` is the default domain.
+type CacheKey = (Option<Uuid>, String);
+
 #[derive(Clone)]
 pub struct LinkCache {
-    cache: Arc<Cache<String, Link>>,
+    cache: Arc<Cache<CacheKey, Link>>,
 }
 
 impl LinkCache {
@@ -20,16 +24,28 @@ impl LinkCache {
         }
     }
 
-    pub async fn get(&self, key: &str) -> Option<Link> {
-        self.cache.get(key).await
+    /// Links that have used up their click budget are never served from the cache.
+    pub async fn get(&self, domain_id: Option<Uuid>, key: &str) -> Option<Link> {
+        let cache_key = (domain_id, key.to_string());
+        let link = self.cache.get(&cache_key).await?;
+        if link.is_exhausted() {
+            self.cache.invalidate(&cache_key).await;
+            return None;
+        }
+        Some(link)
     }
 
-    pub async fn set(&self, key: String, link: Link) {
-        self.cache.insert(key, link).await;
+    pub async fn set(&self, link: Link) {
+        let cache_key = (link.domain_id, link.key.clone());
+        if link.is_exhausted() {
+            self.cache.invalidate(&cache_key).await;
+        } else {
+            self.cache.insert(cache_key, link).await;
+        }
     }
 
-    pub async fn invalidate(&self, key: &str) {
-        self.cache.invalidate(key).await;
+    pub async fn invalidate(&self, domain_id: Option<Uuid>, key: &str) {
+        self.cache.invalidate(&(domain_id, key.to_string())).await;
     }
 
     pub async fn clear(&self) {
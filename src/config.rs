@@ -1,6 +1,10 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
 use anyhow::{Context, Result};
 
+use crate::domain::REDIRECT_STATUS_CODES;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
@@ -10,11 +14,40 @@ pub struct Config {
     pub cache_max_capacity: u64,
     pub rate_limit_per_second: u64,
     pub rate_limit_burst_size: u32,
+    pub rate_limit_redirect_per_second: u64,
+    pub rate_limit_redirect_burst_size: u32,
+    pub rate_limit_qr_per_second: u64,
+    pub rate_limit_qr_burst_size: u32,
+    pub trusted_proxies: Vec<IpNetwork>,
     pub default_redirect_type: u16,
+    pub deleted_link_retention_days: i64,
+    /// HTML file served for links that are not live yet, instead of the built-in page.
+    pub coming_soon_page: Option<PathBuf>,
+    /// Directory with `404.html`, `410.html` and `429.html` replacing the built-in error
+    /// pages browsers get on redirect and QR routes.
+    pub error_pages_dir: Option<PathBuf>,
+    /// Lets links opt in to forwarding the path and query after their key.
+    pub path_forwarding: bool,
+    /// MaxMind country database used to fill `{country}` in destinations and click analytics.
+    pub geoip_database: Option<PathBuf>,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
+        let config = Self::load()?;
+
+        if !REDIRECT_STATUS_CODES.contains(&config.default_redirect_type) {
+            anyhow::bail!(
+                "Invalid DEFAULT_REDIRECT_TYPE {}: expected one of {:?}",
+                config.default_redirect_type,
+                REDIRECT_STATUS_CODES
+            );
+        }
+
+        Ok(config)
+    }
+
+    fn load() -> Result<Self> {
         dotenvy::dotenv().ok();
 
         let host = std::env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
@@ -47,11 +80,114 @@ impl Config {
                 .unwrap_or_else(|_| "50".to_string())
                 .parse()
                 .context("Invalid RATE_LIMIT_BURST_SIZE")?,
+            rate_limit_redirect_per_second: std::env::var("RATE_LIMIT_REDIRECT_PER_SECOND")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .context("Invalid RATE_LIMIT_REDIRECT_PER_SECOND")?,
+            rate_limit_redirect_burst_size: std::env::var("RATE_LIMIT_REDIRECT_BURST_SIZE")
+                .unwrap_or_else(|_| "200".to_string())
+                .parse()
+                .context("Invalid RATE_LIMIT_REDIRECT_BURST_SIZE")?,
+            rate_limit_qr_per_second: std::env::var("RATE_LIMIT_QR_PER_SECOND")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .context("Invalid RATE_LIMIT_QR_PER_SECOND")?,
+            rate_limit_qr_burst_size: std::env::var("RATE_LIMIT_QR_BURST_SIZE")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .context("Invalid RATE_LIMIT_QR_BURST_SIZE")?,
+            trusted_proxies: std::env::var("TRUSTED_PROXIES")
+                .unwrap_or_else(|_| "127.0.0.1,::1".to_string())
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse().map_err(anyhow::Error::msg))
+                .collect::<Result<_>>()
+                .context("Invalid TRUSTED_PROXIES")?,
             default_redirect_type: std::env::var("DEFAULT_REDIRECT_TYPE")
                 .unwrap_or_else(|_| "301".to_string())
                 .parse()
                 .context("Invalid DEFAULT_REDIRECT_TYPE")?,
+            deleted_link_retention_days: std::env::var("DELETED_LINK_RETENTION_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("Invalid DELETED_LINK_RETENTION_DAYS")?,
+            coming_soon_page: std::env::var("COMING_SOON_PAGE").ok().map(PathBuf::from),
+            error_pages_dir: std::env::var("ERROR_PAGES_DIR").ok().map(PathBuf::from),
+            path_forwarding: std::env::var("PATH_FORWARDING")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .context("Invalid PATH_FORWARDING")?,
+            geoip_database: std::env::var("GEOIP_DATABASE").ok().map(PathBuf::from),
         })
     }
 }
 
+/// An address range in CIDR notation (`10.0.0.0/8`, `::1/128`); a bare address is
+/// treated as a single-host range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid address: {}", s))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or_else(|| format!("invalid prefix length: {}", s))?,
+            None => max_prefix,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_network_contains() {
+        let net: IpNetwork = "172.16.0.0/12".parse().unwrap();
+        assert!(net.contains("172.20.1.5".parse().unwrap()));
+        assert!(!net.contains("172.32.0.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let host: IpNetwork = "::1".parse().unwrap();
+        assert!(host.contains("::1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+    }
+}
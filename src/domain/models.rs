@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -11,9 +11,508 @@ pub struct Link {
     pub expires_at: Option<DateTime<Utc>>,
     pub click_count: i64,
     pub owner_id: Option<Uuid>,
+    pub redirect_type: Option<i16>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Argon2 hash of the password visitors must enter before being redirected.
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    /// Clicks after which the link stops redirecting.
+    pub max_clicks: Option<i64>,
+    /// Until this instant the link is scheduled rather than live.
+    pub starts_at: Option<DateTime<Utc>>,
+    /// Where visitors of a scheduled link are sent instead of a "coming soon" page.
+    pub prelaunch_url: Option<String>,
+    /// Where visitors of an expired or used-up link are sent instead of a 410.
+    pub fallback_url: Option<String>,
+    /// Whether the path and query string after the key are carried over to the destination.
+    pub forward_path: bool,
+    /// UTM parameters of the link itself, before folder and owner defaults.
+    #[sqlx(flatten)]
+    pub utm: Utm,
+    pub folder_id: Option<Uuid>,
+    /// The custom domain the key lives on; `None` for the default domain.
+    pub domain_id: Option<Uuid>,
+    /// Hostname of `domain_id`.
+    pub domain: Option<String>,
+}
+
+/// UTM parameters added to a destination at redirect time. Unset fields are left to
+/// defaults, or not added at all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Utm {
+    #[sqlx(rename = "utm_source")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[sqlx(rename = "utm_medium")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>,
+    #[sqlx(rename = "utm_campaign")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<String>,
+    #[sqlx(rename = "utm_term")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+    #[sqlx(rename = "utm_content")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl Utm {
+    pub fn is_empty(&self) -> bool {
+        self.fields().iter().all(|(_, value)| value.is_none())
+    }
+
+    /// These parameters, with unset ones taken from `defaults`.
+    pub fn or(self, defaults: &Utm) -> Utm {
+        Utm {
+            source: self.source.or_else(|| defaults.source.clone()),
+            medium: self.medium.or_else(|| defaults.medium.clone()),
+            campaign: self.campaign.or_else(|| defaults.campaign.clone()),
+            term: self.term.or_else(|| defaults.term.clone()),
+            content: self.content.or_else(|| defaults.content.clone()),
+        }
+    }
+
+    /// Query parameter names and values, in the conventional order.
+    pub fn fields(&self) -> [(&'static str, Option<&str>); 5] {
+        [
+            ("utm_source", self.source.as_deref()),
+            ("utm_medium", self.medium.as_deref()),
+            ("utm_campaign", self.campaign.as_deref()),
+            ("utm_term", self.term.as_deref()),
+            ("utm_content", self.content.as_deref()),
+        ]
+    }
+}
+
+/// The fields supplied when inserting a link; everything else is filled in by the database.
+#[derive(Debug, Clone)]
+pub struct NewLink {
+    pub key: String,
+    pub original_url: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub owner_id: Option<Uuid>,
+    pub redirect_type: Option<i16>,
+    /// Set only for imported links, which keep the creation date and click total they
+    /// had in the system they came from.
+    pub created_at: Option<DateTime<Utc>>,
+    pub click_count: i64,
+    /// Folder name, created for the owner on first use.
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    pub password_hash: Option<String>,
+    pub max_clicks: Option<i64>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub prelaunch_url: Option<String>,
+    pub fallback_url: Option<String>,
+    pub forward_path: bool,
+    pub utm: Utm,
+    pub domain_id: Option<Uuid>,
+}
+
+impl NewLink {
+    /// Whether `link` was inserted for this one, i.e. has the same key on the same domain.
+    pub fn is_same_key(&self, link: &Link) -> bool {
+        self.key == link.key && self.domain_id == link.domain_id
+    }
+}
+
+/// A partial update of a link's mutable attributes. The outer `Option` says whether a
+/// field is being changed; for nullable columns the inner one carries the new value.
+#[derive(Debug, Clone, Default)]
+pub struct LinkUpdate {
+    pub original_url: Option<String>,
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+    pub redirect_type: Option<Option<i16>>,
+    pub folder: Option<Option<String>>,
+    /// Replaces the whole set of tags.
+    pub tags: Option<Vec<String>>,
+    pub password_hash: Option<Option<String>>,
+    pub max_clicks: Option<Option<i64>>,
+    pub starts_at: Option<Option<DateTime<Utc>>>,
+    pub prelaunch_url: Option<Option<String>>,
+    pub fallback_url: Option<Option<String>>,
+    pub forward_path: Option<bool>,
+    /// Replaces the link's UTM parameters as a whole.
+    pub utm: Option<Utm>,
+}
+
+/// How a link is organised: its folder and tags, which live outside the `links` row.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkLabels {
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Narrows a link listing. `None` fields do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct LinkFilter {
+    pub owner_id: Option<Uuid>,
+    pub tag: Option<String>,
+    pub folder: Option<String>,
+    /// Destination host, matching subdomains too.
+    pub domain: Option<String>,
+    /// Case-insensitive substring of the destination URL or the key.
+    pub search: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub status: Option<LinkStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStatus {
+    Active,
+    Scheduled,
+    Expired,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    CreatedAt,
+    ClickCount,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkSort {
+    pub field: SortField,
+    pub order: SortOrder,
+}
+
+impl LinkSort {
+    /// Sort value of `link` under this sort, i.e. where a page ending at `link` stops.
+    pub fn cursor_after(&self, link: &Link) -> LinkCursor {
+        let value = match self.field {
+            SortField::CreatedAt => SortValue::CreatedAt(link.created_at),
+            SortField::ClickCount => SortValue::ClickCount(link.click_count),
+        };
+        LinkCursor { order: self.order, value, id: link.id }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortValue {
+    CreatedAt(DateTime<Utc>),
+    ClickCount(i64),
+}
+
+/// Keyset position in a sorted link listing: the next page starts after the link with
+/// this sort value and id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkCursor {
+    pub order: SortOrder,
+    pub value: SortValue,
+    pub id: Uuid,
+}
+
+impl LinkCursor {
+    pub fn sort(&self) -> LinkSort {
+        let field = match self.value {
+            SortValue::CreatedAt(_) => SortField::CreatedAt,
+            SortValue::ClickCount(_) => SortField::ClickCount,
+        };
+        LinkSort { field, order: self.order }
+    }
+}
+
+/// One page of a cursor-paginated listing. `next_cursor` is absent on the last page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// A tag or folder together with how much it is used.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LabelSummary {
+    pub name: String,
+    pub link_count: i64,
+    pub click_count: i64,
+}
+
+/// Defaults a folder gives the links in it.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FolderSettings {
+    pub name: String,
+    /// UTM parameters for links that do not set them, ahead of the owner's defaults.
+    #[sqlx(flatten)]
+    pub utm: Utm,
+}
+
+/// Body of `PATCH /api/v1/folders/{name}`; `null` clears the folder's UTM defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateFolderRequest {
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub utm: Option<Option<Utm>>,
+}
+
+/// Clicks tagged with one `utm_campaign`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CampaignStats {
+    pub campaign: String,
+    pub clicks: i64,
+    pub unique_visitors: i64,
+    /// Links that got clicks for the campaign.
+    pub links: i64,
+}
+
+/// One recorded change to a link's destination or redirect settings.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LinkRevision {
+    pub id: Uuid,
+    pub link_id: Uuid,
+    pub old_url: String,
+    pub new_url: String,
+    pub old_expires_at: Option<DateTime<Utc>>,
+    pub new_expires_at: Option<DateTime<Utc>>,
+    pub old_redirect_type: Option<i16>,
+    pub new_redirect_type: Option<i16>,
+    pub changed_by_owner_id: Uuid,
+    pub changed_by_api_key_id: Uuid,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// HTTP status codes a link may redirect with.
+pub const REDIRECT_STATUS_CODES: [u16; 5] = [301, 302, 303, 307, 308];
+
+pub fn is_permanent_redirect(status: u16) -> bool {
+    matches!(status, 301 | 308)
+}
+
+/// Where a link stands at a given moment, which decides how a visit to it is answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// `starts_at` has not been reached yet.
+    Scheduled,
+    Active,
+    /// `expires_at` has passed.
+    Expired,
+    /// Every click allowed by `max_clicks` has been used.
+    Exhausted,
+    /// In the trash.
+    Deleted,
 }
 
 impl Link {
+    pub fn state_at(&self, now: DateTime<Utc>) -> LinkState {
+        if self.is_deleted() {
+            LinkState::Deleted
+        } else if self.expires_at.is_some_and(|expires_at| expires_at < now) {
+            LinkState::Expired
+        } else if self.is_exhausted() {
+            LinkState::Exhausted
+        } else if self.starts_at.is_some_and(|starts_at| starts_at > now) {
+            LinkState::Scheduled
+        } else {
+            LinkState::Active
+        }
+    }
+
+    /// Whether the link has used up its click budget.
+    pub fn is_exhausted(&self) -> bool {
+        self.max_clicks.is_some_and(|max_clicks| self.click_count >= max_clicks)
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    pub fn is_password_protected(&self) -> bool {
+        self.password_hash.is_some()
+    }
+}
+
+/// What a visit to a short link leads to.
+#[derive(Debug, Clone)]
+pub enum LinkVisit {
+    /// The link itself, live or scheduled.
+    Open(Box<Link>),
+    /// The link is expired or used up; visitors go to its fallback destination instead.
+    Fallback(String),
+}
+
+/// A short domain of its own an owner serves links from, besides the deployment's.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Domain {
+    pub id: Uuid,
+    pub hostname: String,
+    pub owner_id: Uuid,
+    /// What the owner publishes to prove they control the hostname.
+    pub verification_token: String,
+    /// Unset while the domain is pending; pending domains serve no links.
+    pub verified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Domain {
+    pub fn is_verified(&self) -> bool {
+        self.verified_at.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainStatus {
+    Pending,
+    Verified,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DomainResponse {
+    pub id: Uuid,
+    pub hostname: String,
+    pub owner_id: Uuid,
+    pub status: DomainStatus,
+    /// How to verify the domain; only while it is pending.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<DomainVerification>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Either of these proves control of a domain.
+#[derive(Debug, Serialize)]
+pub struct DomainVerification {
+    pub token: String,
+    pub txt_name: String,
+    pub txt_value: String,
+    pub well_known_url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateDomainRequest {
+    pub hostname: String,
+}
+
+/// Defaults an owner sets once for all of their links.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OwnerSettings {
+    pub owner_id: Uuid,
+    /// Used for expired links without a `fallback_url` of their own.
+    pub fallback_url: Option<String>,
+    /// UTM parameters for links that set neither them nor their folder.
+    #[sqlx(flatten)]
+    pub utm: Utm,
+    /// Unset until the owner first changes a setting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A partial update of an owner's settings, nested like `LinkUpdate`.
+#[derive(Debug, Clone, Default)]
+pub struct OwnerSettingsUpdate {
+    pub fallback_url: Option<Option<String>>,
+    /// Replaces the owner's default UTM parameters as a whole.
+    pub utm: Option<Utm>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub key: String,
+    pub owner_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub is_active: bool,
+    pub role: String,
+    #[sqlx(flatten)]
+    pub quotas: QuotaLimits,
+}
+
+/// Per-key usage caps; `None` means unlimited for that metric and period.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QuotaLimits {
+    pub daily_links_quota: Option<i64>,
+    pub monthly_links_quota: Option<i64>,
+    pub daily_analytics_quota: Option<i64>,
+    pub monthly_analytics_quota: Option<i64>,
+    pub daily_qr_quota: Option<i64>,
+    pub monthly_qr_quota: Option<i64>,
+}
+
+impl QuotaLimits {
+    pub fn limit(&self, metric: UsageMetric, period: UsagePeriod) -> Option<i64> {
+        match (metric, period) {
+            (UsageMetric::LinksCreated, UsagePeriod::Day) => self.daily_links_quota,
+            (UsageMetric::LinksCreated, UsagePeriod::Month) => self.monthly_links_quota,
+            (UsageMetric::AnalyticsQueries, UsagePeriod::Day) => self.daily_analytics_quota,
+            (UsageMetric::AnalyticsQueries, UsagePeriod::Month) => self.monthly_analytics_quota,
+            (UsageMetric::QrRenders, UsagePeriod::Day) => self.daily_qr_quota,
+            (UsageMetric::QrRenders, UsagePeriod::Month) => self.monthly_qr_quota,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageMetric {
+    LinksCreated,
+    AnalyticsQueries,
+    QrRenders,
+}
+
+impl UsageMetric {
+    pub const ALL: [UsageMetric; 3] = [
+        UsageMetric::LinksCreated,
+        UsageMetric::AnalyticsQueries,
+        UsageMetric::QrRenders,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsageMetric::LinksCreated => "links_created",
+            UsageMetric::AnalyticsQueries => "analytics_queries",
+            UsageMetric::QrRenders => "qr_renders",
+        }
+    }
+}
+
+/// Quota windows, aligned to UTC calendar days and months.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsagePeriod {
+    Day,
+    Month,
+}
+
+impl UsagePeriod {
+    pub const ALL: [UsagePeriod; 2] = [UsagePeriod::Day, UsagePeriod::Month];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsagePeriod::Day => "day",
+            UsagePeriod::Month => "month",
+        }
+    }
+
+    pub fn start(&self, now: DateTime<Utc>) -> NaiveDate {
+        let today = now.date_naive();
+        match self {
+            UsagePeriod::Day => today,
+            UsagePeriod::Month => today.with_day(1).unwrap_or(today),
+        }
+    }
+
+    pub fn resets_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let start = self.start(now);
+        let next = match self {
+            UsagePeriod::Day => start.succ_opt(),
+            UsagePeriod::Month => start.checked_add_months(Months::new(1)),
+        };
+        next.unwrap_or(start).and_time(chrono::NaiveTime::MIN).and_utc()
+    }
+}
+
+impl ApiKey {
     pub fn is_expired(&self) -> bool {
         if let Some(expires_at) = self.expires_at {
             expires_at < Utc::now()
@@ -21,6 +520,31 @@ impl Link {
             false
         }
     }
+
+    pub fn is_usable(&self) -> bool {
+        self.is_active && !self.is_expired()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == API_KEY_ROLE_ADMIN
+    }
+}
+
+pub const API_KEY_ROLE_ADMIN: &str = "admin";
+
+/// The caller resolved from a valid API key, injected into handlers by the auth middleware.
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub api_key_id: Uuid,
+    pub owner_id: Uuid,
+    pub is_admin: bool,
+    pub quotas: QuotaLimits,
+}
+
+impl AuthContext {
+    pub fn can_access(&self, link: &Link) -> bool {
+        self.is_admin || link.owner_id == Some(self.owner_id)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,7 +555,232 @@ pub struct CreateLinkRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_in: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub owner_id: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_type: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Visitors have to enter this before being redirected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// The link stops redirecting after this many clicks; `1` makes a single-use link.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<i64>,
+    /// The link goes live at this instant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
+    /// Where visitors are sent before `starts_at`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prelaunch_url: Option<String>,
+    /// Where visitors are sent once the link has expired or run out of clicks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_url: Option<String>,
+    /// Appends whatever follows the key in a visit, `/{key}/rest?x=1`, to the destination.
+    #[serde(default)]
+    pub forward_path: bool,
+    /// UTM parameters added to the destination, ahead of folder and owner defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utm: Option<Utm>,
+    /// Hostname of a registered custom domain to create the link on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchCreateRequest {
+    pub links: Vec<CreateLinkRequest>,
+    /// When set, either every link is created or none is.
+    #[serde(default)]
+    pub all_or_nothing: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchCreateResponse {
+    pub created: usize,
+    pub failed: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<LinkResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+/// One row of a `POST /api/v1/links/import` upload, either a CSV record or an NDJSON line.
+/// Column names match the export, so an exported file can be imported again.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImportRow {
+    pub url: String,
+    #[serde(default, alias = "custom_alias")]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub redirect_type: Option<u16>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub click_count: Option<i64>,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Argon2 hash from an export, so protected links keep their password.
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    #[serde(default)]
+    pub max_clicks: Option<i64>,
+    #[serde(default)]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub prelaunch_url: Option<String>,
+    #[serde(default)]
+    pub fallback_url: Option<String>,
+    #[serde(default)]
+    pub forward_path: bool,
+    #[serde(default)]
+    pub utm: Option<Utm>,
+}
+
+impl ImportRow {
+    /// Splits the row into the fields any new link has and the history it brings along.
+    pub fn into_parts(self) -> (CreateLinkRequest, ImportedHistory) {
+        let request = CreateLinkRequest {
+            url: self.url,
+            custom_alias: self.alias,
+            expires_in: self.expires_in,
+            expires_at: self.expires_at,
+            redirect_type: self.redirect_type,
+            folder: self.folder,
+            tags: self.tags,
+            password: None,
+            max_clicks: self.max_clicks,
+            starts_at: self.starts_at,
+            prelaunch_url: self.prelaunch_url,
+            fallback_url: self.fallback_url,
+            forward_path: self.forward_path,
+            utm: self.utm,
+            domain: self.domain,
+        };
+        let history = ImportedHistory {
+            created_at: self.created_at,
+            click_count: self.click_count.unwrap_or(0),
+            password_hash: self.password_hash,
+        };
+        (request, history)
+    }
+}
+
+/// What an imported link keeps from where it was exported rather than getting anew:
+/// its creation date, click total and password hash.
+#[derive(Debug, Clone, Default)]
+pub struct ImportedHistory {
+    pub created_at: Option<DateTime<Utc>>,
+    pub click_count: i64,
+    pub password_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    /// Per-row failures, capped so a bad file cannot produce an unbounded response.
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRowError {
+    /// 1-based line of the record in the uploaded file (the CSV header is line 1).
+    pub line: usize,
+    pub error: ErrorResponse,
+}
+
+/// A link as written by `GET /api/v1/links/export`, with everything needed to import
+/// it again as it was.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub alias: String,
+    pub url: String,
+    pub short_url: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub redirect_type: u16,
+    pub click_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    /// Hostname of the custom domain the key lives on; `None` for the default domain.
+    pub domain: Option<String>,
+    pub password_hash: Option<String>,
+    pub max_clicks: Option<i64>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub prelaunch_url: Option<String>,
+    pub fallback_url: Option<String>,
+    pub forward_path: bool,
+    pub utm: Option<Utm>,
+}
+
+/// Body of `PATCH /api/v1/links/{key}`. Omitted fields are left untouched; `null`
+/// clears `expires_at`, `folder`, `password`, `max_clicks`, `starts_at`, `prelaunch_url`,
+/// `fallback_url` or `utm`, or resets `redirect_type` to the deployment default. `tags`
+/// and `utm` replace the link's tags and UTM parameters as a whole, and `forward_path`
+/// switches path forwarding on or off.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateLinkRequest {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub redirect_type: Option<Option<u16>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub folder: Option<Option<String>>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub password: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub max_clicks: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub starts_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub prelaunch_url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub fallback_url: Option<Option<String>>,
+    #[serde(default)]
+    pub forward_path: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub utm: Option<Option<Utm>>,
+}
+
+/// Body of `PATCH /api/v1/settings`. Omitted fields are left untouched; `null` clears them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateSettingsRequest {
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub fallback_url: Option<Option<String>>,
+    /// Replaces the default UTM parameters as a whole.
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub utm: Option<Option<Utm>>,
+}
+
+/// Distinguishes a field sent as `null` (`Some(None)`) from one left out (`None`).
+fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +792,34 @@ pub struct LinkResponse {
     pub created_at: DateTime<Utc>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_at: Option<DateTime<Utc>>,
+    pub redirect_type: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    pub password_protected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prelaunch_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_url: Option<String>,
+    pub forward_path: bool,
+    /// The link's own UTM parameters, without defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utm: Option<Utm>,
+    /// Custom domain of the link; absent on the default domain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedLinkResponse {
+    #[serde(flatten)]
+    pub link: LinkResponse,
+    pub deleted_at: DateTime<Utc>,
+    pub purge_after: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +830,10 @@ pub struct LinkStats {
     pub created_at: DateTime<Utc>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -118,9 +899,31 @@ pub struct TimeSeriesPoint {
     pub unique_visitors: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub api_key_id: Uuid,
+    pub metrics: Vec<MetricUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricUsage {
+    pub metric: String,
+    pub daily: QuotaUsage,
+    pub monthly: QuotaUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    pub used: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    pub resets_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    pub code: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<String>,
 }
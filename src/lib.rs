@@ -6,8 +6,15 @@ pub mod observability;
 pub mod repository;
 pub mod services;
 
-#[cfg(test)]
+#[doc(hidden)]
 pub async fn create_test_app() -> axum::Router {
+    let probe = services::NetworkProbe::new().expect("Failed to build verification probe");
+    create_test_app_with_probe(std::sync::Arc::new(probe)).await
+}
+
+/// The test app, looking for domain verification tokens through `probe`.
+#[doc(hidden)]
+pub async fn create_test_app_with_probe(probe: std::sync::Arc<dyn services::VerificationProbe>) -> axum::Router {
     use std::sync::Arc;
     use sqlx::postgres::PgPoolOptions;
 
@@ -26,14 +33,44 @@ pub async fn create_test_app() -> axum::Router {
         .expect("Failed to run migrations");
 
     let cache = cache::LinkCache::new(100, 60);
-    let repository = repository::LinkRepository::new(db_pool);
+    let repository = repository::LinkRepository::new(db_pool.clone());
+    let domain_service = services::DomainService::new(
+        repository::DomainRepository::new(db_pool.clone()),
+        probe,
+        "http://localhost:8080",
+    );
     let link_service = Arc::new(services::LinkService::new(
-        repository,
+        repository.clone(),
         cache,
+        domain_service.clone(),
         "http://localhost:8080".to_string(),
+        302,
+        chrono::Duration::days(30),
+        true,
+    ));
+    let auth_service = Arc::new(services::AuthService::new(
+        repository::ApiKeyRepository::new(db_pool.clone()),
     ));
+    let usage_service = Arc::new(services::UsageService::new(
+        repository::UsageRepository::new(db_pool),
+    ));
+
+    let trusted_proxies = Arc::new(Vec::new());
+    let bucket = api::rate_limit::BucketConfig { per_second: 100, burst: 1000 };
+    let rate_limiter =
+        api::rate_limit::RateLimiter::new(bucket, bucket, bucket, trusted_proxies.clone());
 
-    let app_state = api::AppState { link_service };
+    let app_state = api::AppState {
+        link_service,
+        domain_service: Arc::new(domain_service),
+        auth_service,
+        usage_service,
+        repository,
+        rate_limiter,
+        trusted_proxies,
+        pages: Arc::new(api::pages::PageTemplates::default()),
+        geoip: Arc::new(services::GeoIp::default()),
+    };
     api::create_router(app_state)
 }
 
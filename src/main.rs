@@ -1,16 +1,9 @@
-mod api;
-mod cache;
-mod config;
-mod domain;
-mod observability;
-mod repository;
-mod services;
-
 use anyhow::Result;
 use axum::{http::Method, middleware as axum_middleware, routing::get};
 use sqlx::postgres::PgPoolOptions;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tower::ServiceBuilder;
 use tower_http::{
     compression::CompressionLayer,
@@ -18,15 +11,22 @@ use tower_http::{
     trace::TraceLayer,
 };
 
-use crate::{
-    api::{create_router, AppState},
+use rustyshort::{
+    api::{
+        create_router,
+        pages::PageTemplates,
+        rate_limit::{BucketConfig, RateLimiter},
+        AppState,
+    },
     cache::LinkCache,
     config::Config,
     observability::{init_logging, setup_metrics_recorder, track_metrics},
-    repository::LinkRepository,
-    services::LinkService,
+    repository::{ApiKeyRepository, DomainRepository, LinkRepository, UsageRepository},
+    services::{AuthService, DomainService, GeoIp, LinkService, NetworkProbe, UsageService},
 };
 
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     init_logging();
@@ -51,22 +51,79 @@ async fn main() -> Result<()> {
     );
 
     let repository = LinkRepository::new(db_pool.clone());
+    let domain_service = DomainService::new(
+        DomainRepository::new(db_pool.clone()),
+        Arc::new(NetworkProbe::new()?),
+        &config.base_url,
+    );
     let link_service = Arc::new(LinkService::new(
         repository.clone(),
         cache,
+        domain_service.clone(),
         config.base_url.clone(),
+        config.default_redirect_type,
+        chrono::Duration::days(config.deleted_link_retention_days),
+        config.path_forwarding,
     ));
 
+    tokio::spawn({
+        let link_service = link_service.clone();
+        async move {
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match link_service.purge_deleted().await {
+                    Ok(0) => {}
+                    Ok(purged) => tracing::info!("Purged {} deleted links", purged),
+                    Err(e) => tracing::error!("Failed to purge deleted links: {:?}", e),
+                }
+            }
+        }
+    });
+
+    let auth_service = Arc::new(AuthService::new(ApiKeyRepository::new(db_pool.clone())));
+    let usage_service = Arc::new(UsageService::new(UsageRepository::new(db_pool.clone())));
+
+    let trusted_proxies = Arc::new(config.trusted_proxies.clone());
+    let rate_limiter = RateLimiter::new(
+        BucketConfig {
+            per_second: config.rate_limit_redirect_per_second,
+            burst: config.rate_limit_redirect_burst_size,
+        },
+        BucketConfig {
+            per_second: config.rate_limit_per_second,
+            burst: config.rate_limit_burst_size,
+        },
+        BucketConfig {
+            per_second: config.rate_limit_qr_per_second,
+            burst: config.rate_limit_qr_burst_size,
+        },
+        trusted_proxies.clone(),
+    );
+
+    let pages = Arc::new(PageTemplates::load(
+        config.coming_soon_page.as_deref(),
+        config.error_pages_dir.as_deref(),
+    )?);
+    let geoip = Arc::new(GeoIp::open(config.geoip_database.as_deref())?);
+
     let app_state = AppState {
         link_service,
+        domain_service: Arc::new(domain_service),
+        auth_service,
+        usage_service,
         repository,
+        rate_limiter,
+        trusted_proxies,
+        pages,
+        geoip,
     };
 
     let metrics_handle = setup_metrics_recorder();
     tracing::info!("Metrics recorder initialized");
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_origin(Any)
         .allow_headers(Any);
 
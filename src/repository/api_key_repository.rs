@@ -0,0 +1,30 @@
+use sqlx::{PgPool, Result};
+use crate::domain::ApiKey;
+
+#[derive(Clone)]
+pub struct ApiKeyRepository {
+    pool: PgPool,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_by_key(&self, key: &str) -> Result<Option<ApiKey>> {
+        let api_key = sqlx::query_as::<_, ApiKey>(
+            r#"
+            SELECT id, key, owner_id, created_at, expires_at, is_active, role,
+                   daily_links_quota, monthly_links_quota, daily_analytics_quota,
+                   monthly_analytics_quota, daily_qr_quota, monthly_qr_quota
+            FROM api_keys
+            WHERE key = $1
+            "#
+        )
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(api_key)
+    }
+}
@@ -0,0 +1,79 @@
+use sqlx::{PgPool, Result};
+use uuid::Uuid;
+use crate::domain::Domain;
+
+#[derive(Clone)]
+pub struct DomainRepository {
+    pool: PgPool,
+}
+
+impl DomainRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Registers `hostname` for `owner_id`, pending verification. Returns `None` if it is
+    /// already taken.
+    pub async fn create(&self, hostname: &str, owner_id: Uuid, verification_token: &str) -> Result<Option<Domain>> {
+        let domain = sqlx::query_as::<_, Domain>(
+            r#"
+            INSERT INTO domains (hostname, owner_id, verification_token)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (hostname) DO NOTHING
+            RETURNING id, hostname, owner_id, verification_token, verified_at, created_at
+            "#
+        )
+        .bind(hostname)
+        .bind(owner_id)
+        .bind(verification_token)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(domain)
+    }
+
+    /// Marks a domain verified, keeping the time it was first verified.
+    pub async fn mark_verified(&self, domain_id: Uuid) -> Result<Domain> {
+        let domain = sqlx::query_as::<_, Domain>(
+            r#"
+            UPDATE domains
+            SET verified_at = COALESCE(verified_at, NOW())
+            WHERE id = $1
+            RETURNING id, hostname, owner_id, verification_token, verified_at, created_at
+            "#
+        )
+        .bind(domain_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(domain)
+    }
+
+    pub async fn find_by_hostname(&self, hostname: &str) -> Result<Option<Domain>> {
+        let domain = sqlx::query_as::<_, Domain>(
+            "SELECT id, hostname, owner_id, verification_token, verified_at, created_at FROM domains WHERE hostname = $1"
+        )
+        .bind(hostname)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(domain)
+    }
+
+    /// Domains of `owner_id`, or of every owner when `None`, by hostname.
+    pub async fn list(&self, owner_id: Option<Uuid>) -> Result<Vec<Domain>> {
+        let domains = sqlx::query_as::<_, Domain>(
+            r#"
+            SELECT id, hostname, owner_id, verification_token, verified_at, created_at
+            FROM domains
+            WHERE $1::uuid IS NULL OR owner_id = $1
+            ORDER BY hostname
+            "#
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(domains)
+    }
+}
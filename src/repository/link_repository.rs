@@ -1,7 +1,47 @@
 use chrono::{DateTime, Utc};
-use sqlx::{PgPool, Result};
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder, Result};
+use std::collections::HashMap;
 use uuid::Uuid;
-use crate::domain::{Link, LinkAnalytics};
+use crate::domain::{
+    AuthContext, CampaignStats, FolderSettings, LabelSummary, Link, LinkAnalytics, LinkCursor,
+    LinkFilter, LinkLabels, LinkRevision, LinkSort, LinkStatus, LinkUpdate, NewLink, OwnerSettings,
+    OwnerSettingsUpdate, SortField, SortOrder, SortValue, Utm,
+};
+use crate::services::AnalyticsData;
+
+pub struct BatchInsert {
+    pub inserted: Vec<Link>,
+    /// Domain and key of every link that was not inserted because the key was taken.
+    pub conflicts: Vec<(Option<Uuid>, String)>,
+}
+
+/// Which clicks an analytics query covers.
+pub enum AnalyticsScope<'a> {
+    Link(Uuid),
+    /// Every live link carrying the tag, across all owners when `owner_id` is `None`.
+    Tag { name: &'a str, owner_id: Option<Uuid> },
+}
+
+impl AnalyticsScope<'_> {
+    /// Appends the condition on `links l` that selects this scope.
+    fn push_filter(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        match *self {
+            AnalyticsScope::Link(link_id) => {
+                query.push(" l.id = ").push_bind(link_id);
+            }
+            AnalyticsScope::Tag { name, owner_id } => {
+                query.push(
+                    " l.deleted_at IS NULL AND l.id IN (SELECT lt.link_id FROM link_tags lt JOIN tags t ON t.id = lt.tag_id WHERE t.name = ",
+                );
+                query.push_bind(name.to_string());
+                if let Some(owner_id) = owner_id {
+                    query.push(" AND t.owner_id = ").push_bind(owner_id);
+                }
+                query.push(")");
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct LinkRepository {
@@ -13,131 +53,769 @@ impl LinkRepository {
         Self { pool }
     }
 
-    pub async fn create(&self, key: String, original_url: String, expires_at: Option<DateTime<Utc>>, owner_id: Option<Uuid>) -> Result<Link> {
+    pub async fn create(&self, new_link: NewLink) -> Result<Link> {
+        let mut tx = self.pool.begin().await?;
+
+        let folder_id = match (new_link.owner_id, &new_link.folder) {
+            (Some(owner_id), Some(name)) => Some(folder_id(&mut tx, owner_id, name).await?),
+            _ => None,
+        };
+
         let link = sqlx::query_as::<_, Link>(
             r#"
-            INSERT INTO links (key, original_url, expires_at, owner_id)
-            VALUES ($1, $2, $3, $4)
-            RETURNING id, key, original_url, created_at, expires_at, click_count, owner_id
+            INSERT INTO links (
+                key, original_url, expires_at, owner_id, redirect_type, created_at, click_count,
+                folder_id, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path,
+                utm_source, utm_medium, utm_campaign, utm_term, utm_content, domain_id
+            )
+            VALUES ($1, $2, $3, $4, $5, COALESCE($6, NOW()), $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
+            RETURNING id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
             "#
         )
-        .bind(&key)
-        .bind(&original_url)
-        .bind(expires_at)
-        .bind(owner_id)
-        .fetch_one(&self.pool)
+        .bind(&new_link.key)
+        .bind(&new_link.original_url)
+        .bind(new_link.expires_at)
+        .bind(new_link.owner_id)
+        .bind(new_link.redirect_type)
+        .bind(new_link.created_at)
+        .bind(new_link.click_count)
+        .bind(folder_id)
+        .bind(&new_link.password_hash)
+        .bind(new_link.max_clicks)
+        .bind(new_link.starts_at)
+        .bind(&new_link.prelaunch_url)
+        .bind(&new_link.fallback_url)
+        .bind(new_link.forward_path)
+        .bind(&new_link.utm.source)
+        .bind(&new_link.utm.medium)
+        .bind(&new_link.utm.campaign)
+        .bind(&new_link.utm.term)
+        .bind(&new_link.utm.content)
+        .bind(new_link.domain_id)
+        .fetch_one(&mut *tx)
         .await?;
 
+        attach_tags(&mut tx, &vec![link.id; new_link.tags.len()], &new_link.tags).await?;
+
+        tx.commit().await?;
         Ok(link)
     }
 
-    pub async fn find_by_key(&self, key: &str) -> Result<Option<Link>> {
+    /// Inserts `links` with a single multi-row statement. Links whose key is already taken
+    /// on their domain are skipped and reported in `conflicts`; with `all_or_nothing` any conflict rolls the
+    /// whole batch back, leaving `inserted` empty. Links without a creation date of their
+    /// own are stamped a microsecond apart in the order given, so that listings and exports
+    /// ordered by `created_at` keep the batch's order.
+    pub async fn create_batch(&self, links: &[NewLink], all_or_nothing: bool) -> Result<BatchInsert> {
+        let keys: Vec<&str> = links.iter().map(|l| l.key.as_str()).collect();
+        let urls: Vec<&str> = links.iter().map(|l| l.original_url.as_str()).collect();
+        let expires_at: Vec<Option<DateTime<Utc>>> = links.iter().map(|l| l.expires_at).collect();
+        let owner_ids: Vec<Option<Uuid>> = links.iter().map(|l| l.owner_id).collect();
+        let redirect_types: Vec<Option<i16>> = links.iter().map(|l| l.redirect_type).collect();
+        let created_at: Vec<Option<DateTime<Utc>>> = links.iter().map(|l| l.created_at).collect();
+        let click_counts: Vec<i64> = links.iter().map(|l| l.click_count).collect();
+        let password_hashes: Vec<Option<&str>> = links.iter().map(|l| l.password_hash.as_deref()).collect();
+        let max_clicks: Vec<Option<i64>> = links.iter().map(|l| l.max_clicks).collect();
+        let starts_at: Vec<Option<DateTime<Utc>>> = links.iter().map(|l| l.starts_at).collect();
+        let prelaunch_urls: Vec<Option<&str>> = links.iter().map(|l| l.prelaunch_url.as_deref()).collect();
+        let fallback_urls: Vec<Option<&str>> = links.iter().map(|l| l.fallback_url.as_deref()).collect();
+        let forward_paths: Vec<bool> = links.iter().map(|l| l.forward_path).collect();
+        let utm_sources: Vec<Option<&str>> = links.iter().map(|l| l.utm.source.as_deref()).collect();
+        let utm_media: Vec<Option<&str>> = links.iter().map(|l| l.utm.medium.as_deref()).collect();
+        let utm_campaigns: Vec<Option<&str>> = links.iter().map(|l| l.utm.campaign.as_deref()).collect();
+        let utm_terms: Vec<Option<&str>> = links.iter().map(|l| l.utm.term.as_deref()).collect();
+        let utm_contents: Vec<Option<&str>> = links.iter().map(|l| l.utm.content.as_deref()).collect();
+        let domain_ids: Vec<Option<Uuid>> = links.iter().map(|l| l.domain_id).collect();
+
+        let mut tx = self.pool.begin().await?;
+
+        let mut folders: HashMap<(Uuid, &str), Uuid> = HashMap::new();
+        let mut folder_ids: Vec<Option<Uuid>> = Vec::with_capacity(links.len());
+        for link in links {
+            let folder = match (link.owner_id, link.folder.as_deref()) {
+                (Some(owner_id), Some(name)) => match folders.get(&(owner_id, name)) {
+                    Some(id) => Some(*id),
+                    None => {
+                        let id = folder_id(&mut tx, owner_id, name).await?;
+                        folders.insert((owner_id, name), id);
+                        Some(id)
+                    }
+                },
+                _ => None,
+            };
+            folder_ids.push(folder);
+        }
+
+        let inserted = sqlx::query_as::<_, Link>(
+            r#"
+            INSERT INTO links (
+                key, original_url, expires_at, owner_id, redirect_type, created_at, click_count,
+                folder_id, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path,
+                utm_source, utm_medium, utm_campaign, utm_term, utm_content, domain_id
+            )
+            SELECT key, url, expires_at, owner_id, redirect_type,
+                   COALESCE(created_at, NOW() + (position - 1) * INTERVAL '1 microsecond'), click_count,
+                   folder_id, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path,
+                utm_source, utm_medium, utm_campaign, utm_term, utm_content, domain_id
+            FROM UNNEST(
+                $1::VARCHAR[], $2::TEXT[], $3::TIMESTAMPTZ[], $4::UUID[], $5::SMALLINT[],
+                $6::TIMESTAMPTZ[], $7::BIGINT[], $8::UUID[], $9::TEXT[], $10::BIGINT[],
+                $11::TIMESTAMPTZ[], $12::TEXT[], $13::TEXT[], $14::BOOLEAN[],
+                $15::TEXT[], $16::TEXT[], $17::TEXT[], $18::TEXT[], $19::TEXT[], $20::UUID[]
+            ) WITH ORDINALITY AS t(
+                key, url, expires_at, owner_id, redirect_type, created_at, click_count,
+                folder_id, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path,
+                utm_source, utm_medium, utm_campaign, utm_term, utm_content, domain_id, position
+            )
+            ON CONFLICT DO NOTHING
+            RETURNING id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
+            "#
+        )
+        .bind(&keys)
+        .bind(&urls)
+        .bind(&expires_at)
+        .bind(&owner_ids)
+        .bind(&redirect_types)
+        .bind(&created_at)
+        .bind(&click_counts)
+        .bind(&folder_ids)
+        .bind(&password_hashes)
+        .bind(&max_clicks)
+        .bind(&starts_at)
+        .bind(&prelaunch_urls)
+        .bind(&fallback_urls)
+        .bind(&forward_paths)
+        .bind(&utm_sources)
+        .bind(&utm_media)
+        .bind(&utm_campaigns)
+        .bind(&utm_terms)
+        .bind(&utm_contents)
+        .bind(&domain_ids)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let (tagged, tags): (Vec<Uuid>, Vec<String>) = inserted
+            .iter()
+            .filter_map(|link| Some((link.id, links.iter().find(|l| l.is_same_key(link))?)))
+            .flat_map(|(id, new_link)| new_link.tags.iter().map(move |tag| (id, tag.clone())))
+            .unzip();
+        attach_tags(&mut tx, &tagged, &tags).await?;
+
+        let conflicts: Vec<(Option<Uuid>, String)> = links
+            .iter()
+            .filter(|l| !inserted.iter().any(|i| l.is_same_key(i)))
+            .map(|l| (l.domain_id, l.key.clone()))
+            .collect();
+
+        if all_or_nothing && !conflicts.is_empty() {
+            tx.rollback().await?;
+            return Ok(BatchInsert { inserted: Vec::new(), conflicts });
+        }
+
+        tx.commit().await?;
+        Ok(BatchInsert { inserted, conflicts })
+    }
+
+    /// Looks up `key` on a custom domain, or on the default one when `domain_id` is `None`.
+    pub async fn find_by_key(&self, domain_id: Option<Uuid>, key: &str) -> Result<Option<Link>> {
         let link = sqlx::query_as::<_, Link>(
             r#"
-            SELECT id, key, original_url, created_at, expires_at, click_count, owner_id
+            SELECT id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
             FROM links
-            WHERE key = $1
+            WHERE key = $1 AND domain_id IS NOT DISTINCT FROM $2
             "#
         )
         .bind(key)
+        .bind(domain_id)
         .fetch_optional(&self.pool)
         .await?;
 
         Ok(link)
     }
 
-    pub async fn exists(&self, key: &str) -> Result<bool> {
+    /// Applies `update` and, in the same transaction, records a revision if the
+    /// destination, expiry or redirect type actually changed.
+    pub async fn update(&self, link_id: Uuid, update: LinkUpdate, changed_by: &AuthContext) -> Result<Option<Link>> {
+        let mut tx = self.pool.begin().await?;
+
+        let before = sqlx::query_as::<_, Link>(
+            r#"
+            SELECT id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
+            FROM links
+            WHERE id = $1
+            FOR UPDATE
+            "#
+        )
+        .bind(link_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(before) = before else {
+            return Ok(None);
+        };
+
+        // Folders belong to the link's owner, whoever makes the change.
+        let folder_id = match (&update.folder, before.owner_id) {
+            (Some(Some(name)), Some(owner_id)) => Some(folder_id(&mut tx, owner_id, name).await?),
+            _ => None,
+        };
+
+        let utm = update.utm.clone().unwrap_or_default();
+        let after = sqlx::query_as::<_, Link>(
+            r#"
+            UPDATE links SET
+                original_url = COALESCE($2, original_url),
+                expires_at = CASE WHEN $3 THEN $4 ELSE expires_at END,
+                redirect_type = CASE WHEN $5 THEN $6 ELSE redirect_type END,
+                folder_id = CASE WHEN $7 THEN $8 ELSE folder_id END,
+                password_hash = CASE WHEN $9 THEN $10 ELSE password_hash END,
+                max_clicks = CASE WHEN $11 THEN $12 ELSE max_clicks END,
+                starts_at = CASE WHEN $13 THEN $14 ELSE starts_at END,
+                prelaunch_url = CASE WHEN $15 THEN $16 ELSE prelaunch_url END,
+                fallback_url = CASE WHEN $17 THEN $18 ELSE fallback_url END,
+                forward_path = COALESCE($19, forward_path),
+                utm_source = CASE WHEN $20 THEN $21 ELSE utm_source END,
+                utm_medium = CASE WHEN $20 THEN $22 ELSE utm_medium END,
+                utm_campaign = CASE WHEN $20 THEN $23 ELSE utm_campaign END,
+                utm_term = CASE WHEN $20 THEN $24 ELSE utm_term END,
+                utm_content = CASE WHEN $20 THEN $25 ELSE utm_content END
+            WHERE id = $1
+            RETURNING id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
+            "#
+        )
+        .bind(before.id)
+        .bind(update.original_url)
+        .bind(update.expires_at.is_some())
+        .bind(update.expires_at.flatten())
+        .bind(update.redirect_type.is_some())
+        .bind(update.redirect_type.flatten())
+        .bind(update.folder.is_some())
+        .bind(folder_id)
+        .bind(update.password_hash.is_some())
+        .bind(update.password_hash.flatten())
+        .bind(update.max_clicks.is_some())
+        .bind(update.max_clicks.flatten())
+        .bind(update.starts_at.is_some())
+        .bind(update.starts_at.flatten())
+        .bind(update.prelaunch_url.is_some())
+        .bind(update.prelaunch_url.flatten())
+        .bind(update.fallback_url.is_some())
+        .bind(update.fallback_url.flatten())
+        .bind(update.forward_path)
+        .bind(update.utm.is_some())
+        .bind(utm.source)
+        .bind(utm.medium)
+        .bind(utm.campaign)
+        .bind(utm.term)
+        .bind(utm.content)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if let Some(tags) = update.tags {
+            sqlx::query("DELETE FROM link_tags WHERE link_id = $1")
+                .bind(after.id)
+                .execute(&mut *tx)
+                .await?;
+            attach_tags(&mut tx, &vec![after.id; tags.len()], &tags).await?;
+        }
+
+        if before.original_url != after.original_url
+            || before.expires_at != after.expires_at
+            || before.redirect_type != after.redirect_type
+        {
+            sqlx::query(
+                r#"
+                INSERT INTO link_revisions (
+                    link_id, old_url, new_url, old_expires_at, new_expires_at,
+                    old_redirect_type, new_redirect_type, changed_by_owner_id, changed_by_api_key_id
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                "#
+            )
+            .bind(after.id)
+            .bind(&before.original_url)
+            .bind(&after.original_url)
+            .bind(before.expires_at)
+            .bind(after.expires_at)
+            .bind(before.redirect_type)
+            .bind(after.redirect_type)
+            .bind(changed_by.owner_id)
+            .bind(changed_by.api_key_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(Some(after))
+    }
+
+    pub async fn list_revisions(&self, link_id: Uuid, limit: i64) -> Result<Vec<LinkRevision>> {
+        let revisions = sqlx::query_as::<_, LinkRevision>(
+            r#"
+            SELECT id, link_id, old_url, new_url, old_expires_at, new_expires_at,
+                   old_redirect_type, new_redirect_type, changed_by_owner_id,
+                   changed_by_api_key_id, changed_at
+            FROM link_revisions
+            WHERE link_id = $1
+            ORDER BY changed_at DESC, id DESC
+            LIMIT $2
+            "#
+        )
+        .bind(link_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(revisions)
+    }
+
+    pub async fn find_revision(&self, link_id: Uuid, revision_id: Uuid) -> Result<Option<LinkRevision>> {
+        let revision = sqlx::query_as::<_, LinkRevision>(
+            r#"
+            SELECT id, link_id, old_url, new_url, old_expires_at, new_expires_at,
+                   old_redirect_type, new_redirect_type, changed_by_owner_id,
+                   changed_by_api_key_id, changed_at
+            FROM link_revisions
+            WHERE link_id = $1 AND id = $2
+            "#
+        )
+        .bind(link_id)
+        .bind(revision_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(revision)
+    }
+
+    pub async fn exists(&self, domain_id: Option<Uuid>, key: &str) -> Result<bool> {
         let exists: bool = sqlx::query_scalar(
-            "SELECT EXISTS(SELECT 1 FROM links WHERE key = $1)"
+            "SELECT EXISTS(SELECT 1 FROM links WHERE key = $1 AND domain_id IS NOT DISTINCT FROM $2)"
         )
         .bind(key)
+        .bind(domain_id)
         .fetch_one(&self.pool)
         .await?;
 
         Ok(exists)
     }
 
-    pub async fn increment_click_count(&self, key: &str) -> Result<()> {
+    pub async fn increment_click_count(&self, link_id: Uuid) -> Result<()> {
         sqlx::query(
-            "UPDATE links SET click_count = click_count + 1 WHERE key = $1"
+            "UPDATE links SET click_count = click_count + 1 WHERE id = $1"
         )
-        .bind(key)
+        .bind(link_id)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn delete(&self, key: &str) -> Result<bool> {
+    /// Counts a click on a link unless its click budget is used up. The check and the
+    /// increment are one statement, so concurrent redirects can never take the count past
+    /// `max_clicks`. Returns the updated link, or `None` if no click was left.
+    pub async fn claim_click(&self, link_id: Uuid) -> Result<Option<Link>> {
+        let link = sqlx::query_as::<_, Link>(
+            r#"
+            UPDATE links SET click_count = click_count + 1
+            WHERE id = $1 AND deleted_at IS NULL AND (max_clicks IS NULL OR click_count < max_clicks)
+            RETURNING id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
+            "#
+        )
+        .bind(link_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    /// Moves a link to the trash. The row (and its analytics) stays in place, which also
+    /// keeps the key reserved until the link is purged.
+    pub async fn soft_delete(&self, link_id: Uuid) -> Result<bool> {
         let result = sqlx::query(
-            "DELETE FROM links WHERE key = $1"
+            "UPDATE links SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL"
         )
-        .bind(key)
+        .bind(link_id)
         .execute(&self.pool)
         .await?;
 
         Ok(result.rows_affected() > 0)
     }
 
-    pub async fn list(&self, limit: i64, offset: i64) -> Result<Vec<Link>> {
-        let links = sqlx::query_as::<_, Link>(
+    pub async fn restore(&self, link_id: Uuid) -> Result<Option<Link>> {
+        let link = sqlx::query_as::<_, Link>(
             r#"
-            SELECT id, key, original_url, created_at, expires_at, click_count, owner_id
-            FROM links
-            ORDER BY created_at DESC
-            LIMIT $1 OFFSET $2
+            UPDATE links SET deleted_at = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
             "#
         )
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(&self.pool)
+        .bind(link_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(link)
+    }
+
+    /// Permanently removes links that have been in the trash since before `deleted_before`,
+    /// freeing their keys for reuse. Their clicks stay in `link_analytics`, detached.
+    pub async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM links WHERE deleted_at IS NOT NULL AND deleted_at < $1"
+        )
+        .bind(deleted_before)
+        .execute(&self.pool)
         .await?;
 
+        Ok(result.rows_affected())
+    }
+
+    /// One page of live links matching `filter`, or of trashed ones with `deleted`. Rows
+    /// come in `sort` order, newest first without one (most recently deleted first in
+    /// the trash), always with `id` breaking ties. `after` continues a listing past a
+    /// cursor issued for the same `sort`.
+    pub async fn list(
+        &self,
+        filter: &LinkFilter,
+        sort: Option<LinkSort>,
+        after: Option<&LinkCursor>,
+        deleted: bool,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Link>> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            r#"
+            SELECT id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
+            FROM links
+            WHERE
+            "#
+        );
+
+        query.push(if deleted { "deleted_at IS NOT NULL" } else { "deleted_at IS NULL" });
+        push_link_filter(&mut query, filter);
+        if let Some(cursor) = after {
+            let comparison = match cursor.order {
+                SortOrder::Asc => ">",
+                SortOrder::Desc => "<",
+            };
+            match cursor.value {
+                SortValue::CreatedAt(created_at) => {
+                    query.push(format!(" AND (created_at, id) {comparison} (")).push_bind(created_at);
+                }
+                SortValue::ClickCount(click_count) => {
+                    query.push(format!(" AND (click_count, id) {comparison} (")).push_bind(click_count);
+                }
+            }
+            query.push(", ").push_bind(cursor.id).push(")");
+        }
+
+        match sort {
+            Some(sort) => {
+                let column = match sort.field {
+                    SortField::CreatedAt => "created_at",
+                    SortField::ClickCount => "click_count",
+                };
+                let direction = match sort.order {
+                    SortOrder::Asc => "ASC",
+                    SortOrder::Desc => "DESC",
+                };
+                query.push(format!(" ORDER BY {column} {direction}, id {direction}"));
+            }
+            None if deleted => {
+                query.push(" ORDER BY deleted_at DESC, id DESC");
+            }
+            None => {
+                query.push(" ORDER BY created_at DESC, id DESC");
+            }
+        }
+        query.push(" LIMIT ").push_bind(limit);
+        query.push(" OFFSET ").push_bind(offset);
+
+        let links = query.build_query_as::<Link>().fetch_all(&self.pool).await?;
+
         Ok(links)
     }
 
-    pub async fn record_analytics(
+    /// Live links in `(created_at, id)` order, starting after the given position. Used to
+    /// walk an owner's whole inventory one page at a time.
+    pub async fn list_after(
         &self,
-        link_id: Uuid,
-        referrer: Option<String>,
-        user_agent: Option<String>,
-        ip_hash: Option<String>,
-        browser: Option<String>,
-        os: Option<String>,
-        device_type: Option<String>,
-    ) -> Result<()> {
-        sqlx::query(
+        owner_id: Option<Uuid>,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<Link>> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            r#"
+            SELECT id, key, original_url, created_at, expires_at, click_count, owner_id, redirect_type, deleted_at, password_hash, max_clicks, starts_at, prelaunch_url, fallback_url, forward_path, utm_source, utm_medium, utm_campaign, utm_term, utm_content, folder_id, domain_id,
+                   (SELECT hostname FROM domains WHERE domains.id = links.domain_id) AS domain
+            FROM links
+            WHERE deleted_at IS NULL
+            "#
+        );
+
+        if let Some(owner_id) = owner_id {
+            query.push(" AND owner_id = ").push_bind(owner_id);
+        }
+        if let Some((created_at, id)) = after {
+            query.push(" AND (created_at, id) > (").push_bind(created_at);
+            query.push(", ").push_bind(id).push(")");
+        }
+        query.push(" ORDER BY created_at, id LIMIT ").push_bind(limit);
+
+        let links = query.build_query_as::<Link>().fetch_all(&self.pool).await?;
+
+        Ok(links)
+    }
+
+    /// Folder and tags of each of `link_ids`; links without either are left out.
+    pub async fn labels(&self, link_ids: &[Uuid]) -> Result<HashMap<Uuid, LinkLabels>> {
+        let rows = sqlx::query_as::<_, (Uuid, Option<String>, Vec<String>)>(
+            r#"
+            SELECT l.id, f.name,
+                   COALESCE(ARRAY_AGG(t.name ORDER BY t.name) FILTER (WHERE t.name IS NOT NULL), '{}')
+            FROM links l
+            LEFT JOIN folders f ON f.id = l.folder_id
+            LEFT JOIN link_tags lt ON lt.link_id = l.id
+            LEFT JOIN tags t ON t.id = lt.tag_id
+            WHERE l.id = ANY($1) AND (l.folder_id IS NOT NULL OR lt.tag_id IS NOT NULL)
+            GROUP BY l.id, f.name
+            "#
+        )
+        .bind(link_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, folder, tags)| (id, LinkLabels { folder, tags }))
+            .collect())
+    }
+
+    pub async fn list_tags(&self, owner_id: Option<Uuid>) -> Result<Vec<LabelSummary>> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            r#"
+            SELECT t.name, COUNT(l.id) AS link_count, COALESCE(SUM(l.click_count), 0)::BIGINT AS click_count
+            FROM tags t
+            LEFT JOIN link_tags lt ON lt.tag_id = t.id
+            LEFT JOIN links l ON l.id = lt.link_id AND l.deleted_at IS NULL
+            "#
+        );
+
+        if let Some(owner_id) = owner_id {
+            query.push(" WHERE t.owner_id = ").push_bind(owner_id);
+        }
+        query.push(" GROUP BY t.name ORDER BY t.name");
+
+        let tags = query.build_query_as::<LabelSummary>().fetch_all(&self.pool).await?;
+
+        Ok(tags)
+    }
+
+    pub async fn list_folders(&self, owner_id: Option<Uuid>) -> Result<Vec<LabelSummary>> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            r#"
+            SELECT f.name, COUNT(l.id) AS link_count, COALESCE(SUM(l.click_count), 0)::BIGINT AS click_count
+            FROM folders f
+            LEFT JOIN links l ON l.folder_id = f.id AND l.deleted_at IS NULL
+            "#
+        );
+
+        if let Some(owner_id) = owner_id {
+            query.push(" WHERE f.owner_id = ").push_bind(owner_id);
+        }
+        query.push(" GROUP BY f.name ORDER BY f.name");
+
+        let folders = query.build_query_as::<LabelSummary>().fetch_all(&self.pool).await?;
+
+        Ok(folders)
+    }
+
+    /// Sets the UTM defaults of one of the owner's folders, creating it on first use.
+    pub async fn update_folder_utm(&self, owner_id: Uuid, name: &str, utm: &Utm) -> Result<FolderSettings> {
+        let folder = sqlx::query_as::<_, FolderSettings>(
             r#"
-            INSERT INTO link_analytics (link_id, referrer, user_agent, ip_hash, browser, os, device_type)
+            INSERT INTO folders (owner_id, name, utm_source, utm_medium, utm_campaign, utm_term, utm_content)
             VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (owner_id, name) DO UPDATE SET
+                utm_source = EXCLUDED.utm_source,
+                utm_medium = EXCLUDED.utm_medium,
+                utm_campaign = EXCLUDED.utm_campaign,
+                utm_term = EXCLUDED.utm_term,
+                utm_content = EXCLUDED.utm_content
+            RETURNING name, utm_source, utm_medium, utm_campaign, utm_term, utm_content
+            "#
+        )
+        .bind(owner_id)
+        .bind(name)
+        .bind(&utm.source)
+        .bind(&utm.medium)
+        .bind(&utm.campaign)
+        .bind(&utm.term)
+        .bind(&utm.content)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(folder)
+    }
+
+    /// The UTM defaults a link in `folder_id` gets: the folder's, then the owner's, per field.
+    pub async fn utm_defaults(&self, owner_id: Uuid, folder_id: Option<Uuid>) -> Result<Utm> {
+        let utm = sqlx::query_as::<_, Utm>(
+            r#"
+            SELECT COALESCE(f.utm_source, s.utm_source) AS utm_source,
+                   COALESCE(f.utm_medium, s.utm_medium) AS utm_medium,
+                   COALESCE(f.utm_campaign, s.utm_campaign) AS utm_campaign,
+                   COALESCE(f.utm_term, s.utm_term) AS utm_term,
+                   COALESCE(f.utm_content, s.utm_content) AS utm_content
+            FROM (SELECT $1::UUID AS owner_id) o
+            LEFT JOIN owner_settings s ON s.owner_id = o.owner_id
+            LEFT JOIN folders f ON f.id = $2 AND f.owner_id = o.owner_id
+            "#
+        )
+        .bind(owner_id)
+        .bind(folder_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(utm)
+    }
+
+    /// Clicks of the last `days` days by the campaign they were tagged with, across all
+    /// owners when `owner_id` is `None`.
+    pub async fn campaign_stats(&self, owner_id: Option<Uuid>, days: i32) -> Result<Vec<CampaignStats>> {
+        let campaigns = sqlx::query_as::<_, CampaignStats>(
+            r#"
+            SELECT la.utm_campaign AS campaign,
+                   COUNT(*) AS clicks,
+                   COUNT(DISTINCT la.ip_hash) AS unique_visitors,
+                   COUNT(DISTINCT la.link_id) AS links
+            FROM link_analytics la
+            JOIN links l ON la.link_id = l.id
+            WHERE la.utm_campaign IS NOT NULL
+              AND la.clicked_at >= NOW() - INTERVAL '1 day' * $2
+              AND ($1::UUID IS NULL OR l.owner_id = $1)
+            GROUP BY la.utm_campaign
+            ORDER BY clicks DESC, campaign
+            "#
+        )
+        .bind(owner_id)
+        .bind(days)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(campaigns)
+    }
+
+    pub async fn tag_exists(&self, name: &str, owner_id: Option<Uuid>) -> Result<bool> {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM tags WHERE name = $1 AND ($2::UUID IS NULL OR owner_id = $2))"
+        )
+        .bind(name)
+        .bind(owner_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    pub async fn find_owner_settings(&self, owner_id: Uuid) -> Result<Option<OwnerSettings>> {
+        let settings = sqlx::query_as::<_, OwnerSettings>(
+            "SELECT owner_id, fallback_url, utm_source, utm_medium, utm_campaign, utm_term, utm_content, updated_at FROM owner_settings WHERE owner_id = $1"
+        )
+        .bind(owner_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(settings)
+    }
+
+    /// Applies `update` to the owner's settings, creating them on first use.
+    pub async fn update_owner_settings(&self, owner_id: Uuid, update: OwnerSettingsUpdate) -> Result<OwnerSettings> {
+        let utm = update.utm.clone().unwrap_or_default();
+        let settings = sqlx::query_as::<_, OwnerSettings>(
+            r#"
+            INSERT INTO owner_settings (owner_id, fallback_url, utm_source, utm_medium, utm_campaign, utm_term, utm_content)
+            VALUES ($1, $3, $5, $6, $7, $8, $9)
+            ON CONFLICT (owner_id) DO UPDATE SET
+                fallback_url = CASE WHEN $2 THEN EXCLUDED.fallback_url ELSE owner_settings.fallback_url END,
+                utm_source = CASE WHEN $4 THEN EXCLUDED.utm_source ELSE owner_settings.utm_source END,
+                utm_medium = CASE WHEN $4 THEN EXCLUDED.utm_medium ELSE owner_settings.utm_medium END,
+                utm_campaign = CASE WHEN $4 THEN EXCLUDED.utm_campaign ELSE owner_settings.utm_campaign END,
+                utm_term = CASE WHEN $4 THEN EXCLUDED.utm_term ELSE owner_settings.utm_term END,
+                utm_content = CASE WHEN $4 THEN EXCLUDED.utm_content ELSE owner_settings.utm_content END,
+                updated_at = NOW()
+            RETURNING owner_id, fallback_url, utm_source, utm_medium, utm_campaign, utm_term, utm_content, updated_at
+            "#
+        )
+        .bind(owner_id)
+        .bind(update.fallback_url.is_some())
+        .bind(update.fallback_url.flatten())
+        .bind(update.utm.is_some())
+        .bind(utm.source)
+        .bind(utm.medium)
+        .bind(utm.campaign)
+        .bind(utm.term)
+        .bind(utm.content)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(settings)
+    }
+
+    pub async fn record_analytics(&self, link_id: Uuid, data: AnalyticsData) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO link_analytics
+                (id, link_id, referrer, user_agent, ip_hash, browser, os, device_type, country_code, utm_campaign)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             "#
         )
+        .bind(data.click_id)
         .bind(link_id)
-        .bind(referrer)
-        .bind(user_agent)
-        .bind(ip_hash)
-        .bind(browser)
-        .bind(os)
-        .bind(device_type)
+        .bind(data.referrer)
+        .bind(data.user_agent)
+        .bind(data.ip_hash)
+        .bind(data.browser)
+        .bind(data.os)
+        .bind(data.device_type)
+        .bind(data.country_code)
+        .bind(data.campaign)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn get_analytics(&self, key: &str, limit: i64) -> Result<Vec<LinkAnalytics>> {
+    /// Clicks on a link, newest first, continuing after the `(clicked_at, id)` position
+    /// when given.
+    pub async fn get_analytics(
+        &self,
+        link_id: Uuid,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<LinkAnalytics>> {
+        let (after_clicked_at, after_id) = after.unzip();
         let analytics = sqlx::query_as::<_, LinkAnalytics>(
             r#"
             SELECT la.id, la.link_id, la.clicked_at, la.referrer, la.user_agent, 
                    la.ip_hash, la.country_code, la.browser, la.os, la.device_type, la.city
             FROM link_analytics la
-            JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1
-            ORDER BY la.clicked_at DESC
-            LIMIT $2
+            WHERE la.link_id = $1
+              AND ($2::timestamptz IS NULL OR (la.clicked_at, la.id) < ($2, $3))
+            ORDER BY la.clicked_at DESC, la.id DESC
+            LIMIT $4
             "#
         )
-        .bind(key)
+        .bind(link_id)
+        .bind(after_clicked_at)
+        .bind(after_id)
         .bind(limit)
         .fetch_all(&self.pool)
         .await?;
@@ -145,118 +823,108 @@ impl LinkRepository {
         Ok(analytics)
     }
     
-    pub async fn get_total_clicks(&self, key: &str) -> Result<i64> {
-        let count: i64 = sqlx::query_scalar(
+    pub async fn get_total_clicks(&self, scope: &AnalyticsScope<'_>) -> Result<i64> {
+        let mut query = QueryBuilder::<Postgres>::new(
             r#"
             SELECT COUNT(*)
             FROM link_analytics la
             JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1
+            WHERE
             "#
-        )
-        .bind(key)
-        .fetch_one(&self.pool)
-        .await?;
+        );
+        scope.push_filter(&mut query);
+
+        let count: i64 = query.build_query_scalar().fetch_one(&self.pool).await?;
 
         Ok(count)
     }
     
-    pub async fn get_unique_visitors(&self, key: &str) -> Result<i64> {
-        let count: i64 = sqlx::query_scalar(
+    pub async fn get_unique_visitors(&self, scope: &AnalyticsScope<'_>) -> Result<i64> {
+        let mut query = QueryBuilder::<Postgres>::new(
             r#"
             SELECT COUNT(DISTINCT ip_hash)
             FROM link_analytics la
             JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1 AND la.ip_hash IS NOT NULL
+            WHERE la.ip_hash IS NOT NULL AND
             "#
-        )
-        .bind(key)
-        .fetch_one(&self.pool)
-        .await?;
+        );
+        scope.push_filter(&mut query);
+
+        let count: i64 = query.build_query_scalar().fetch_one(&self.pool).await?;
 
         Ok(count)
     }
     
-    pub async fn get_top_referrers(&self, key: &str, limit: i64) -> Result<Vec<(String, i64)>> {
-        let referrers = sqlx::query_as::<_, (String, i64)>(
+    pub async fn get_top_referrers(&self, scope: &AnalyticsScope<'_>, limit: i64) -> Result<Vec<(String, i64)>> {
+        let mut query = QueryBuilder::<Postgres>::new(
             r#"
             SELECT la.referrer, COUNT(*) as count
             FROM link_analytics la
             JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1 AND la.referrer IS NOT NULL AND la.referrer != ''
-            GROUP BY la.referrer
-            ORDER BY count DESC
-            LIMIT $2
+            WHERE la.referrer IS NOT NULL AND la.referrer != '' AND
             "#
-        )
-        .bind(key)
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await?;
+        );
+        scope.push_filter(&mut query);
+        query.push(" GROUP BY la.referrer ORDER BY count DESC LIMIT ").push_bind(limit);
+
+        let referrers = query.build_query_as::<(String, i64)>().fetch_all(&self.pool).await?;
 
         Ok(referrers)
     }
     
-    pub async fn get_device_breakdown(&self, key: &str) -> Result<Vec<(String, i64)>> {
-        let devices = sqlx::query_as::<_, (String, i64)>(
+    pub async fn get_device_breakdown(&self, scope: &AnalyticsScope<'_>) -> Result<Vec<(String, i64)>> {
+        let mut query = QueryBuilder::<Postgres>::new(
             r#"
             SELECT COALESCE(la.device_type, 'other') as device, COUNT(*) as count
             FROM link_analytics la
             JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1
-            GROUP BY device
-            ORDER BY count DESC
+            WHERE
             "#
-        )
-        .bind(key)
-        .fetch_all(&self.pool)
-        .await?;
+        );
+        scope.push_filter(&mut query);
+        query.push(" GROUP BY device ORDER BY count DESC");
+
+        let devices = query.build_query_as::<(String, i64)>().fetch_all(&self.pool).await?;
 
         Ok(devices)
     }
     
-    pub async fn get_browser_stats(&self, key: &str, limit: i64) -> Result<Vec<(String, i64)>> {
-        let browsers = sqlx::query_as::<_, (String, i64)>(
+    pub async fn get_browser_stats(&self, scope: &AnalyticsScope<'_>, limit: i64) -> Result<Vec<(String, i64)>> {
+        let mut query = QueryBuilder::<Postgres>::new(
             r#"
             SELECT la.browser, COUNT(*) as count
             FROM link_analytics la
             JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1 AND la.browser IS NOT NULL
-            GROUP BY la.browser
-            ORDER BY count DESC
-            LIMIT $2
+            WHERE la.browser IS NOT NULL AND
             "#
-        )
-        .bind(key)
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await?;
+        );
+        scope.push_filter(&mut query);
+        query.push(" GROUP BY la.browser ORDER BY count DESC LIMIT ").push_bind(limit);
+
+        let browsers = query.build_query_as::<(String, i64)>().fetch_all(&self.pool).await?;
 
         Ok(browsers)
     }
     
-    pub async fn get_country_stats(&self, key: &str, limit: i64) -> Result<Vec<(String, i64)>> {
-        let countries = sqlx::query_as::<_, (String, i64)>(
+    pub async fn get_country_stats(&self, scope: &AnalyticsScope<'_>, limit: i64) -> Result<Vec<(String, i64)>> {
+        let mut query = QueryBuilder::<Postgres>::new(
             r#"
             SELECT la.country_code, COUNT(*) as count
             FROM link_analytics la
             JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1 AND la.country_code IS NOT NULL
-            GROUP BY la.country_code
-            ORDER BY count DESC
-            LIMIT $2
+            WHERE la.country_code IS NOT NULL AND
             "#
-        )
-        .bind(key)
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await?;
+        );
+        scope.push_filter(&mut query);
+        query.push(" GROUP BY la.country_code ORDER BY count DESC LIMIT ").push_bind(limit);
+
+        let countries = query.build_query_as::<(String, i64)>().fetch_all(&self.pool).await?;
 
         Ok(countries)
     }
     
-    pub async fn get_time_series(&self, key: &str, days: i32) -> Result<Vec<(String, i64, i64)>> {
-        let time_series = sqlx::query_as::<_, (String, i64, i64)>(
+    pub async fn get_time_series(&self, scope: &AnalyticsScope<'_>, days: i32) -> Result<Vec<(String, i64, i64)>> {
+        let mut query = QueryBuilder::<Postgres>::new(
             r#"
             SELECT 
                 DATE(la.clicked_at)::TEXT as date,
@@ -264,28 +932,122 @@ impl LinkRepository {
                 COUNT(DISTINCT la.ip_hash)::BIGINT as unique_visitors
             FROM link_analytics la
             JOIN links l ON la.link_id = l.id
-            WHERE l.key = $1 
-                AND la.clicked_at >= NOW() - INTERVAL '1 day' * $2
-            GROUP BY DATE(la.clicked_at)
-            ORDER BY date DESC
+            WHERE la.clicked_at >= NOW() - INTERVAL '1 day' * 
             "#
-        )
-        .bind(key)
-        .bind(days)
-        .fetch_all(&self.pool)
-        .await?;
+        );
+        query.push_bind(days).push(" AND ");
+        scope.push_filter(&mut query);
+        query.push(" GROUP BY DATE(la.clicked_at) ORDER BY date DESC");
+
+        let time_series = query.build_query_as::<(String, i64, i64)>().fetch_all(&self.pool).await?;
 
         Ok(time_series)
     }
+}
 
-    pub async fn cleanup_expired(&self) -> Result<u64> {
-        let result = sqlx::query(
-            "DELETE FROM links WHERE expires_at IS NOT NULL AND expires_at < NOW()"
-        )
-        .execute(&self.pool)
-        .await?;
-
-        Ok(result.rows_affected())
+/// Appends `AND ...` conditions on `links` for every filter that is set.
+fn push_link_filter(query: &mut QueryBuilder<'_, Postgres>, filter: &LinkFilter) {
+    if let Some(owner_id) = filter.owner_id {
+        query.push(" AND owner_id = ").push_bind(owner_id);
     }
+    if let Some(ref tag) = filter.tag {
+        query.push(
+            " AND id IN (SELECT lt.link_id FROM link_tags lt JOIN tags t ON t.id = lt.tag_id WHERE t.name = ",
+        );
+        query.push_bind(tag.clone()).push(")");
+    }
+    if let Some(ref folder) = filter.folder {
+        query.push(" AND folder_id IN (SELECT id FROM folders WHERE name = ");
+        query.push_bind(folder.clone()).push(")");
+    }
+    if let Some(ref domain) = filter.domain {
+        query.push(" AND (link_domain(original_url) = ").push_bind(domain.clone());
+        query.push(" OR link_domain(original_url) LIKE ").push_bind(format!("%.{}", escape_like(domain)));
+        query.push(")");
+    }
+    if let Some(ref search) = filter.search {
+        let pattern = format!("%{}%", escape_like(search));
+        query.push(" AND (original_url ILIKE ").push_bind(pattern.clone());
+        query.push(" OR key ILIKE ").push_bind(pattern).push(")");
+    }
+    if let Some(created_after) = filter.created_after {
+        query.push(" AND created_at >= ").push_bind(created_after);
+    }
+    if let Some(created_before) = filter.created_before {
+        query.push(" AND created_at < ").push_bind(created_before);
+    }
+    match filter.status {
+        Some(LinkStatus::Active) => {
+            query.push(" AND (starts_at IS NULL OR starts_at <= NOW())");
+            query.push(" AND (expires_at IS NULL OR expires_at > NOW())");
+            query.push(" AND (max_clicks IS NULL OR click_count < max_clicks)");
+        }
+        Some(LinkStatus::Scheduled) => {
+            query.push(" AND starts_at > NOW()");
+        }
+        Some(LinkStatus::Expired) => {
+            query.push(" AND (expires_at <= NOW() OR click_count >= max_clicks)");
+        }
+        None => {}
+    }
+}
+
+/// Makes `value` match literally inside a LIKE pattern.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Finds the owner's folder called `name`, creating it on first use.
+async fn folder_id(conn: &mut PgConnection, owner_id: Uuid, name: &str) -> Result<Uuid> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO folders (owner_id, name)
+        VALUES ($1, $2)
+        ON CONFLICT (owner_id, name) DO UPDATE SET name = EXCLUDED.name
+        RETURNING id
+        "#
+    )
+    .bind(owner_id)
+    .bind(name)
+    .fetch_one(conn)
+    .await
 }
 
+/// Tags `link_ids[i]` with `names[i]`, creating tags for the link's owner as needed.
+async fn attach_tags(conn: &mut PgConnection, link_ids: &[Uuid], names: &[String]) -> Result<()> {
+    if link_ids.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO tags (owner_id, name)
+        SELECT DISTINCT l.owner_id, a.name
+        FROM UNNEST($1::UUID[], $2::TEXT[]) AS a(link_id, name)
+        JOIN links l ON l.id = a.link_id
+        WHERE l.owner_id IS NOT NULL
+        ON CONFLICT (owner_id, name) DO NOTHING
+        "#
+    )
+    .bind(link_ids)
+    .bind(names)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO link_tags (link_id, tag_id)
+        SELECT a.link_id, t.id
+        FROM UNNEST($1::UUID[], $2::TEXT[]) AS a(link_id, name)
+        JOIN links l ON l.id = a.link_id
+        JOIN tags t ON t.owner_id = l.owner_id AND t.name = a.name
+        ON CONFLICT DO NOTHING
+        "#
+    )
+    .bind(link_ids)
+    .bind(names)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
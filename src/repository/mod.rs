@@ -1,4 +1,9 @@
+pub mod api_key_repository;
+pub mod domain_repository;
 pub mod link_repository;
+pub mod usage_repository;
 
-pub use link_repository::LinkRepository;
-
+pub use api_key_repository::ApiKeyRepository;
+pub use domain_repository::DomainRepository;
+pub use link_repository::{AnalyticsScope, BatchInsert, LinkRepository};
+pub use usage_repository::{UsageRepository, UsageWindow};
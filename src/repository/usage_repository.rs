@@ -0,0 +1,113 @@
+use chrono::NaiveDate;
+use sqlx::{PgPool, Postgres, Result, Transaction};
+use uuid::Uuid;
+use crate::domain::{UsageMetric, UsagePeriod};
+
+/// The window a counter belongs to and the cap enforced on it.
+#[derive(Debug, Clone, Copy)]
+pub struct UsageWindow {
+    pub period: UsagePeriod,
+    pub start: NaiveDate,
+    pub limit: Option<i64>,
+}
+
+#[derive(Clone)]
+pub struct UsageRepository {
+    pool: PgPool,
+}
+
+impl UsageRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Adds `amount` to the counters of every window in one transaction. If any window
+    /// would go over its cap nothing is counted and that window's period is returned.
+    pub async fn consume(&self, api_key_id: Uuid, metric: UsageMetric, amount: i64, windows: &[UsageWindow]) -> Result<Option<UsagePeriod>> {
+        let mut tx = self.pool.begin().await?;
+
+        for window in windows {
+            if !Self::increment_within_limit(&mut tx, api_key_id, metric, amount, window).await? {
+                tx.rollback().await?;
+                return Ok(Some(window.period));
+            }
+        }
+
+        tx.commit().await?;
+        Ok(None)
+    }
+
+    async fn increment_within_limit(
+        tx: &mut Transaction<'_, Postgres>,
+        api_key_id: Uuid,
+        metric: UsageMetric,
+        amount: i64,
+        window: &UsageWindow,
+    ) -> Result<bool> {
+        if window.limit.is_some_and(|limit| amount > limit) {
+            return Ok(false);
+        }
+
+        // The conditional upsert makes check-and-increment a single atomic statement, so
+        // concurrent requests cannot push a counter past its limit.
+        let count: Option<i64> = sqlx::query_scalar(
+            r#"
+            INSERT INTO api_key_usage (api_key_id, metric, period, period_start, count)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (api_key_id, metric, period, period_start)
+            DO UPDATE SET count = api_key_usage.count + $5, updated_at = NOW()
+            WHERE $6::BIGINT IS NULL OR api_key_usage.count + $5 <= $6
+            RETURNING count
+            "#
+        )
+        .bind(api_key_id)
+        .bind(metric.as_str())
+        .bind(window.period.as_str())
+        .bind(window.start)
+        .bind(amount)
+        .bind(window.limit)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        Ok(count.is_some())
+    }
+
+    pub async fn refund(&self, api_key_id: Uuid, metric: UsageMetric, amount: i64, windows: &[UsageWindow]) -> Result<()> {
+        for window in windows {
+            sqlx::query(
+                r#"
+                UPDATE api_key_usage
+                SET count = GREATEST(count - $5, 0), updated_at = NOW()
+                WHERE api_key_id = $1 AND metric = $2 AND period = $3 AND period_start = $4
+                "#
+            )
+            .bind(api_key_id)
+            .bind(metric.as_str())
+            .bind(window.period.as_str())
+            .bind(window.start)
+            .bind(amount)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_counts(&self, api_key_id: Uuid, day: NaiveDate, month: NaiveDate) -> Result<Vec<(String, String, i64)>> {
+        let counts = sqlx::query_as::<_, (String, String, i64)>(
+            r#"
+            SELECT metric, period, count
+            FROM api_key_usage
+            WHERE api_key_id = $1
+                AND ((period = 'day' AND period_start = $2) OR (period = 'month' AND period_start = $3))
+            "#
+        )
+        .bind(api_key_id)
+        .bind(day)
+        .bind(month)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+}
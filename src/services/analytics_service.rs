@@ -1,14 +1,63 @@
 use sha2::{Digest, Sha256};
+use uuid::Uuid;
 use woothee::parser::Parser;
 
 #[derive(Debug, Clone)]
 pub struct AnalyticsData {
+    /// Id of the analytics record, the same one destinations received as `{click_id}`.
+    pub click_id: Uuid,
     pub referrer: Option<String>,
     pub user_agent: Option<String>,
     pub ip_hash: Option<String>,
     pub browser: Option<String>,
     pub os: Option<String>,
     pub device_type: Option<String>,
+    pub country_code: Option<String>,
+    /// `utm_campaign` the destination was tagged with.
+    pub campaign: Option<String>,
+}
+
+/// What a redirect request tells about the visitor. Fills the placeholders of templated
+/// destinations and becomes the click's analytics record.
+#[derive(Debug, Clone)]
+pub struct ClickContext {
+    pub click_id: Uuid,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_hash: Option<String>,
+    pub browser: Option<String>,
+    pub os: Option<String>,
+    pub device_type: Option<String>,
+    /// Uppercase ISO 3166-1 alpha-2 code from GeoIP.
+    pub country_code: Option<String>,
+    /// Lowercase primary subtag of the visitor's preferred language, e.g. `de`.
+    pub language: Option<String>,
+}
+
+impl ClickContext {
+    /// A new click with a fresh id, from the request's headers and the country its
+    /// client IP was located in.
+    pub fn new(
+        referrer: Option<&str>,
+        user_agent: Option<&str>,
+        accept_language: Option<&str>,
+        ip_hash: Option<String>,
+        country_code: Option<String>,
+    ) -> Self {
+        let (browser, os, device_type) =
+            user_agent.map_or((None, None, None), AnalyticsService::parse_user_agent);
+        Self {
+            click_id: Uuid::new_v4(),
+            referrer: referrer.map(str::to_string),
+            user_agent: user_agent.map(str::to_string),
+            ip_hash,
+            browser,
+            os,
+            device_type,
+            country_code,
+            language: accept_language.and_then(AnalyticsService::preferred_language),
+        }
+    }
 }
 
 pub struct AnalyticsService;
@@ -45,6 +94,27 @@ impl AnalyticsService {
         }
     }
     
+    /// The primary subtag of the most preferred language in an `Accept-Language` header,
+    /// lowercased. Wildcards and languages refused with `q=0` are skipped.
+    pub fn preferred_language(accept_language: &str) -> Option<String> {
+        let mut preferred: Option<(f32, &str)> = None;
+        for entry in accept_language.split(',') {
+            let mut parts = entry.split(';').map(str::trim);
+            let tag = parts.next().unwrap_or("");
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+                .unwrap_or(0.0);
+
+            let primary = tag.split('-').next().unwrap_or("");
+            let valid = (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic());
+            if valid && quality > 0.0 && preferred.is_none_or(|(best, _)| quality > best) {
+                preferred = Some((quality, primary));
+            }
+        }
+        preferred.map(|(_, language)| language.to_ascii_lowercase())
+    }
+
     pub fn hash_ip(ip: &str) -> String {
         let mut hasher = Sha256::new();
         hasher.update(ip.as_bytes());
@@ -73,6 +143,16 @@ mod tests {
         assert_eq!(device, Some("desktop".to_string()));
     }
     
+    #[test]
+    fn test_preferred_language() {
+        let language = AnalyticsService::preferred_language;
+        assert_eq!(language("de-CH, de;q=0.9, en;q=0.8"), Some("de".to_string()));
+        assert_eq!(language("en;q=0.5, FR-ca;q=0.9, *;q=1"), Some("fr".to_string()));
+        assert_eq!(language("pt-BR;q=0, es;q=0.2"), Some("es".to_string()));
+        assert_eq!(language("*"), None);
+        assert_eq!(language("x-klingon, i"), None);
+    }
+
     #[test]
     fn test_hash_ip() {
         let hash1 = AnalyticsService::hash_ip("192.168.1.1");
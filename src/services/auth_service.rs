@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+
+use crate::{domain::AuthContext, repository::ApiKeyRepository, services::ServiceResult};
+
+/// API keys are stored as the hex-encoded SHA-256 of the token handed to the client,
+/// so a leaked `api_keys` table cannot be replayed against the API.
+#[derive(Clone)]
+pub struct AuthService {
+    repository: ApiKeyRepository,
+}
+
+impl AuthService {
+    pub fn new(repository: ApiKeyRepository) -> Self {
+        Self { repository }
+    }
+
+    pub async fn authenticate(&self, token: &str) -> ServiceResult<Option<AuthContext>> {
+        let api_key = match self.repository.find_by_key(&Self::hash_key(token)).await? {
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+
+        if !api_key.is_usable() {
+            tracing::debug!("Rejected inactive or expired API key {}", api_key.id);
+            return Ok(None);
+        }
+
+        Ok(Some(AuthContext {
+            api_key_id: api_key.id,
+            owner_id: api_key.owner_id,
+            is_admin: api_key.is_admin(),
+            quotas: api_key.quotas,
+        }))
+    }
+
+    pub fn hash_key(token: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(token.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_key() {
+        let hash = AuthService::hash_key("rs_live_secret");
+        assert_eq!(hash, AuthService::hash_key("rs_live_secret"));
+        assert_ne!(hash, AuthService::hash_key("rs_live_other"));
+        assert_eq!(hash.len(), 64);
+    }
+}
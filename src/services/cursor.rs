@@ -0,0 +1,111 @@
+//! Opaque page tokens for keyset pagination.
+//!
+//! A token records the position of the last row on a page (its sort value and id) so the
+//! next page continues right after it, no matter how many rows were inserted or removed
+//! in the meantime. Clients must treat tokens as opaque.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{ServiceError, ServiceResult};
+use crate::domain::{LinkCursor, SortOrder, SortValue};
+
+pub fn encode_link_cursor(cursor: &LinkCursor) -> String {
+    let order = match cursor.order {
+        SortOrder::Asc => "asc",
+        SortOrder::Desc => "desc",
+    };
+    let (field, value) = match cursor.value {
+        SortValue::CreatedAt(created_at) => ("created_at", created_at.timestamp_micros()),
+        SortValue::ClickCount(clicks) => ("click_count", clicks),
+    };
+    encode(&[field, order, &value.to_string(), &cursor.id.to_string()])
+}
+
+pub fn decode_link_cursor(token: &str) -> ServiceResult<LinkCursor> {
+    let parts = decode(token)?;
+    let [field, order, value, id] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    let order = match order.as_str() {
+        "asc" => SortOrder::Asc,
+        "desc" => SortOrder::Desc,
+        _ => return Err(invalid()),
+    };
+    let value = match field.as_str() {
+        "created_at" => SortValue::CreatedAt(timestamp(value)?),
+        "click_count" => SortValue::ClickCount(value.parse().map_err(|_| invalid())?),
+        _ => return Err(invalid()),
+    };
+    let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+
+    Ok(LinkCursor { order, value, id })
+}
+
+/// Token for a click listing, which is always newest first.
+pub fn encode_click_cursor(clicked_at: DateTime<Utc>, id: Uuid) -> String {
+    encode(&["clicked_at", &clicked_at.timestamp_micros().to_string(), &id.to_string()])
+}
+
+pub fn decode_click_cursor(token: &str) -> ServiceResult<(DateTime<Utc>, Uuid)> {
+    let parts = decode(token)?;
+    let [field, clicked_at, id] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    if field != "clicked_at" {
+        return Err(invalid());
+    }
+    let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+
+    Ok((timestamp(clicked_at)?, id))
+}
+
+fn encode(parts: &[&str]) -> String {
+    URL_SAFE_NO_PAD.encode(parts.join(":"))
+}
+
+fn decode(token: &str) -> ServiceResult<Vec<String>> {
+    let bytes = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+    let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+    Ok(text.split(':').map(str::to_string).collect())
+}
+
+/// Timestamps are stored with microsecond precision, so they round-trip exactly.
+fn timestamp(micros: &str) -> ServiceResult<DateTime<Utc>> {
+    micros
+        .parse()
+        .ok()
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or_else(invalid)
+}
+
+fn invalid() -> ServiceError {
+    ServiceError::Validation("Invalid cursor".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_cursors_round_trip() {
+        let cursor = LinkCursor {
+            order: SortOrder::Asc,
+            value: SortValue::CreatedAt(DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap()),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(decode_link_cursor(&encode_link_cursor(&cursor)).unwrap(), cursor);
+
+        let cursor = LinkCursor { value: SortValue::ClickCount(42), ..cursor };
+        assert_eq!(decode_link_cursor(&encode_link_cursor(&cursor)).unwrap(), cursor);
+    }
+
+    #[test]
+    fn test_rejects_tampered_cursors() {
+        let click = encode_click_cursor(Utc::now(), Uuid::new_v4());
+        assert!(decode_link_cursor(&click).is_err());
+        assert!(decode_click_cursor("not a cursor").is_err());
+        assert!(decode_click_cursor(&URL_SAFE_NO_PAD.encode("clicked_at:x:y")).is_err());
+    }
+}
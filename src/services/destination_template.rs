@@ -0,0 +1,110 @@
+use super::analytics_service::ClickContext;
+
+/// Placeholders a destination may contain, filled per click.
+pub const PLACEHOLDERS: [&str; 4] = ["country", "device", "lang", "click_id"];
+
+/// Whether `destination` contains any placeholder, and so differs from click to click.
+pub fn has_placeholders(destination: &str) -> bool {
+    let mut rest = destination;
+    while let Some((_, end, placeholder)) = next_placeholder(rest) {
+        if placeholder.is_some() {
+            return true;
+        }
+        rest = &rest[end..];
+    }
+    false
+}
+
+/// `destination` with its `{name}` placeholders filled from `click`. A placeholder may
+/// carry a fallback, as in `{country|us}`, used when the click has no value for it;
+/// without one it is left empty. Braces around anything else are kept as they are.
+pub fn expand(destination: &str, click: &ClickContext) -> String {
+    let mut expanded = String::with_capacity(destination.len());
+    let mut rest = destination;
+    while let Some((start, end, placeholder)) = next_placeholder(rest) {
+        expanded.push_str(&rest[..start]);
+        match placeholder {
+            Some(Placeholder { name, fallback }) => {
+                expanded.push_str(value(name, click).as_deref().unwrap_or(fallback))
+            }
+            None => expanded.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+struct Placeholder<'a> {
+    name: &'a str,
+    fallback: &'a str,
+}
+
+/// Finds the next `{...}` in `input`: where it starts and ends, and the placeholder it
+/// holds, if any. A `{` without its own closing brace spans just itself.
+fn next_placeholder(input: &str) -> Option<(usize, usize, Option<Placeholder<'_>>)> {
+    let start = input.find('{')?;
+    let inner = &input[start + 1..];
+    let Some(close) = inner.find(['{', '}']).filter(|&i| inner.as_bytes()[i] == b'}') else {
+        return Some((start, start + 1, None));
+    };
+
+    let (name, fallback) = inner[..close].split_once('|').unwrap_or((&inner[..close], ""));
+    let placeholder = PLACEHOLDERS
+        .contains(&name)
+        .then_some(Placeholder { name, fallback });
+    Some((start, start + close + 2, placeholder))
+}
+
+/// The value for a placeholder. Only codes, ids and the fixed device types are ever
+/// substituted, so nothing here needs escaping.
+fn value(name: &str, click: &ClickContext) -> Option<String> {
+    match name {
+        "country" => click.country_code.as_ref().map(|code| code.to_ascii_lowercase()),
+        "device" => click.device_type.clone(),
+        "lang" => click.language.clone(),
+        "click_id" => Some(click.click_id.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1";
+
+    fn click(country: Option<&str>, accept_language: Option<&str>) -> ClickContext {
+        let mut click = ClickContext::new(None, Some(IPHONE), accept_language, None, None);
+        click.country_code = country.map(str::to_string);
+        click
+    }
+
+    #[test]
+    fn test_placeholders_are_filled() {
+        let click = click(Some("DE"), Some("de-DE,de;q=0.9"));
+        assert_eq!(
+            expand("https://example.com/{lang}/{country}/sale?d={device}&id={click_id}", &click),
+            format!("https://example.com/de/de/sale?d=mobile&id={}", click.click_id)
+        );
+    }
+
+    #[test]
+    fn test_missing_values_use_fallback() {
+        let click = click(None, None);
+        assert_eq!(
+            expand("https://example.com/{lang|en}/{country}x", &click),
+            "https://example.com/en/x"
+        );
+    }
+
+    #[test]
+    fn test_other_braces_are_kept() {
+        let click = click(Some("FR"), None);
+        let url = "https://example.com/{id}/{{country}}?q={country";
+        assert!(!has_placeholders("https://example.com/{id}?q={"));
+        assert!(has_placeholders(url));
+        assert_eq!(expand(url, &click), "https://example.com/{id}/{fr}?q={country");
+        assert_eq!(expand("https://example.com/plain", &click), "https://example.com/plain");
+    }
+}
@@ -0,0 +1,229 @@
+use moka::future::Cache;
+use nanoid::nanoid;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    domain::{AuthContext, CreateDomainRequest, Domain, DomainResponse, DomainStatus, DomainVerification},
+    repository::DomainRepository,
+    services::{
+        domain_verification::{self, VerificationProbe},
+        ServiceError, ServiceResult,
+    },
+};
+
+const HOSTNAME_MAX_LENGTH: usize = 253;
+const LABEL_MAX_LENGTH: usize = 63;
+const VERIFICATION_TOKEN_LENGTH: usize = 32;
+
+/// Custom short domains. Every redirect looks up its `Host`, so lookups are cached,
+/// misses included.
+#[derive(Clone)]
+pub struct DomainService {
+    repository: DomainRepository,
+    probe: Arc<dyn VerificationProbe>,
+    hosts: Cache<String, Option<Domain>>,
+    /// Hostname of `BASE_URL`, which cannot be registered as a custom domain.
+    default_host: Option<String>,
+}
+
+impl DomainService {
+    pub fn new(repository: DomainRepository, probe: Arc<dyn VerificationProbe>, base_url: &str) -> Self {
+        let hosts = Cache::builder()
+            .max_capacity(10_000)
+            .time_to_live(Duration::from_secs(60))
+            .build();
+        let default_host = Url::parse(base_url)
+            .ok()
+            .and_then(|url| url.host_str().map(normalize_hostname));
+
+        Self { repository, probe, hosts, default_host }
+    }
+
+    pub async fn create_domain(&self, request: CreateDomainRequest, owner_id: Uuid) -> ServiceResult<DomainResponse> {
+        let hostname = normalize_hostname(&request.hostname);
+        validate_hostname(&hostname)?;
+        if self.default_host.as_deref() == Some(hostname.as_str()) {
+            return Err(ServiceError::Validation(
+                "hostname is the deployment's own domain".to_string(),
+            ));
+        }
+
+        let token = nanoid!(VERIFICATION_TOKEN_LENGTH, &nanoid::alphabet::SAFE);
+        let domain = self
+            .repository
+            .create(&hostname, owner_id, &token)
+            .await?
+            .ok_or_else(|| ServiceError::Conflict("Domain is already registered".to_string()))?;
+        self.hosts.invalidate(&hostname).await;
+
+        Ok(domain_to_response(domain))
+    }
+
+    pub async fn list_domains(&self, owner_id: Option<Uuid>) -> ServiceResult<Vec<DomainResponse>> {
+        let domains = self.repository.list(owner_id).await?;
+        Ok(domains.into_iter().map(domain_to_response).collect())
+    }
+
+    /// Looks for the domain's token in DNS and at its well-known URL, and starts serving
+    /// links on it once found. Verifying an already verified domain is a no-op.
+    pub async fn verify_domain(&self, hostname: &str, auth: &AuthContext) -> ServiceResult<DomainResponse> {
+        let hostname = normalize_hostname(hostname);
+        let domain = self
+            .repository
+            .find_by_hostname(&hostname)
+            .await?
+            .filter(|d| auth.is_admin || d.owner_id == auth.owner_id)
+            .ok_or_else(|| ServiceError::NotFound("Domain not found".to_string()))?;
+        if domain.is_verified() {
+            return Ok(domain_to_response(domain));
+        }
+
+        let Some(method) = domain_verification::find_token(self.probe.as_ref(), &hostname, &domain.verification_token).await
+        else {
+            return Err(ServiceError::Validation(format!(
+                "verification token not found in a TXT record at {} or at {}",
+                domain_verification::txt_record_name(&hostname),
+                domain_verification::well_known_url(&hostname)
+            )));
+        };
+
+        let domain = self.repository.mark_verified(domain.id).await?;
+        self.hosts.invalidate(&hostname).await;
+        tracing::info!("Verified domain {} via {:?}", hostname, method);
+
+        Ok(domain_to_response(domain))
+    }
+
+    /// The custom domain a request was sent to, from its `Host` header. Requests to the
+    /// deployment's own host, or any host that is not registered, use the default domain;
+    /// hosts still pending verification serve nothing.
+    pub async fn for_host(&self, host: Option<&str>) -> ServiceResult<Option<Domain>> {
+        let Some(host) = host.map(host_without_port) else {
+            return Ok(None);
+        };
+        if self.default_host.as_deref() == Some(host.as_str()) {
+            return Ok(None);
+        }
+        match self.lookup(&host).await? {
+            Some(domain) if !domain.is_verified() => {
+                Err(ServiceError::NotFound("Domain is not verified".to_string()))
+            }
+            domain => Ok(domain),
+        }
+    }
+
+    /// Resolves the `domain` a management request names; `None` is the default domain.
+    pub async fn resolve(&self, hostname: Option<&str>) -> ServiceResult<Option<Domain>> {
+        let Some(hostname) = hostname.map(normalize_hostname) else {
+            return Ok(None);
+        };
+        match self.lookup(&hostname).await? {
+            Some(domain) => Ok(Some(domain)),
+            None => Err(ServiceError::NotFound("Domain not found".to_string())),
+        }
+    }
+
+    async fn lookup(&self, hostname: &str) -> ServiceResult<Option<Domain>> {
+        if let Some(domain) = self.hosts.get(hostname).await {
+            return Ok(domain);
+        }
+
+        let domain = self.repository.find_by_hostname(hostname).await?;
+        self.hosts.insert(hostname.to_string(), domain.clone()).await;
+        Ok(domain)
+    }
+}
+
+fn domain_to_response(domain: Domain) -> DomainResponse {
+    let (status, verification) = if domain.is_verified() {
+        (DomainStatus::Verified, None)
+    } else {
+        let verification = DomainVerification {
+            txt_name: domain_verification::txt_record_name(&domain.hostname),
+            txt_value: domain_verification::txt_record_value(&domain.verification_token),
+            well_known_url: domain_verification::well_known_url(&domain.hostname),
+            token: domain.verification_token,
+        };
+        (DomainStatus::Pending, Some(verification))
+    };
+
+    DomainResponse {
+        id: domain.id,
+        hostname: domain.hostname,
+        owner_id: domain.owner_id,
+        status,
+        verification,
+        verified_at: domain.verified_at,
+        created_at: domain.created_at,
+    }
+}
+
+fn normalize_hostname(hostname: &str) -> String {
+    hostname.trim().trim_end_matches('.').to_lowercase()
+}
+
+/// The hostname of a `Host` header value, without any port.
+fn host_without_port(host: &str) -> String {
+    // A bare IPv6 address has colons of its own; only a bracketed one can carry a port.
+    let host = match host.rsplit_once(':') {
+        Some((name, port))
+            if (name.ends_with(']') || !name.contains(':')) && port.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            name
+        }
+        _ => host,
+    };
+    normalize_hostname(host)
+}
+
+fn validate_hostname(hostname: &str) -> ServiceResult<()> {
+    if hostname.len() > HOSTNAME_MAX_LENGTH {
+        return Err(ServiceError::Validation(format!(
+            "hostname exceeds maximum length of {} characters",
+            HOSTNAME_MAX_LENGTH
+        )));
+    }
+
+    let labels: Vec<&str> = hostname.split('.').collect();
+    let valid = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= LABEL_MAX_LENGTH
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+        && !labels[labels.len() - 1].bytes().all(|b| b.is_ascii_digit());
+    if !valid {
+        return Err(ServiceError::Validation(
+            "hostname must be a fully qualified domain name".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hostname_validation() {
+        for hostname in ["go.brand-a.com", "brnd.b", "x1.example.co.uk"] {
+            assert!(validate_hostname(hostname).is_ok(), "{hostname}");
+        }
+        for hostname in ["localhost", "-go.example.com", "go..example.com", "go_1.example.com", "10.0.0.1", ""] {
+            assert!(validate_hostname(hostname).is_err(), "{hostname}");
+        }
+    }
+
+    #[test]
+    fn test_host_header_port_is_ignored() {
+        assert_eq!(host_without_port("Go.Brand-A.com:8080"), "go.brand-a.com");
+        assert_eq!(host_without_port("go.brand-a.com."), "go.brand-a.com");
+        assert_eq!(host_without_port("[::1]:8080"), "[::1]");
+    }
+}
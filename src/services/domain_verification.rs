@@ -0,0 +1,172 @@
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use hickory_resolver::{config::ResolverConfig, name_server::TokioConnectionProvider, TokioResolver};
+use std::time::Duration;
+
+/// DNS name prefix the verification TXT record is published under.
+pub const TXT_RECORD_PREFIX: &str = "_rustyshort";
+/// Value prefix of the verification TXT record; the token follows it.
+pub const TXT_VALUE_PREFIX: &str = "rustyshort-verification=";
+/// Path of the file on the domain itself whose body is the token.
+pub const WELL_KNOWN_PATH: &str = "/.well-known/rustyshort-verification.txt";
+
+/// Largest well-known file body read; a token is far shorter.
+const MAX_BODY_BYTES: usize = 1024;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How an owner showed they control a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationMethod {
+    DnsTxt,
+    WellKnown,
+}
+
+/// Looks for verification tokens out on the network. Behind a trait so tests can stand in
+/// for DNS and HTTP.
+pub trait VerificationProbe: Send + Sync {
+    /// Every TXT record published at `name`; none if the name does not exist.
+    fn txt_records<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>>>;
+
+    /// The body at `url`, or `None` if it does not answer with a success.
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<String>>>;
+}
+
+/// Asks the system resolver and the domain's own web server.
+pub struct NetworkProbe {
+    resolver: TokioResolver,
+    http: reqwest::Client,
+}
+
+impl NetworkProbe {
+    pub fn new() -> Result<Self> {
+        // Fall back to public resolvers where the system configuration cannot be read.
+        let resolver = match TokioResolver::builder_tokio() {
+            Ok(builder) => builder.build(),
+            Err(e) => {
+                tracing::warn!("Failed to read system DNS configuration: {}", e);
+                TokioResolver::builder_with_config(ResolverConfig::cloudflare(), TokioConnectionProvider::default())
+                    .build()
+            }
+        };
+        let http = reqwest::Client::builder()
+            .timeout(PROBE_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::limited(3))
+            .build()?;
+
+        Ok(Self { resolver, http })
+    }
+}
+
+impl VerificationProbe for NetworkProbe {
+    fn txt_records<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>>> {
+        Box::pin(async move {
+            let lookup = match self.resolver.txt_lookup(name).await {
+                Ok(lookup) => lookup,
+                Err(e) if e.is_no_records_found() => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            // Long values arrive split into character strings, which read as one.
+            Ok(lookup
+                .iter()
+                .map(|txt| txt.txt_data().iter().map(|part| String::from_utf8_lossy(part)).collect())
+                .collect())
+        })
+    }
+
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(async move {
+            let mut response = self.http.get(url).send().await?;
+            if !response.status().is_success() {
+                return Ok(None);
+            }
+
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                body.extend_from_slice(&chunk);
+                if body.len() > MAX_BODY_BYTES {
+                    return Ok(None);
+                }
+            }
+            Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+        })
+    }
+}
+
+/// Where the verification TXT record for `hostname` goes.
+pub fn txt_record_name(hostname: &str) -> String {
+    format!("{}.{}", TXT_RECORD_PREFIX, hostname)
+}
+
+pub fn txt_record_value(token: &str) -> String {
+    format!("{}{}", TXT_VALUE_PREFIX, token)
+}
+
+pub fn well_known_url(hostname: &str) -> String {
+    format!("http://{}{}", hostname, WELL_KNOWN_PATH)
+}
+
+/// Checks DNS first, then the well-known file. Lookup failures count as not found, so a
+/// broken web server does not hide a correct TXT record.
+pub async fn find_token(probe: &dyn VerificationProbe, hostname: &str, token: &str) -> Option<VerificationMethod> {
+    let expected = txt_record_value(token);
+    match probe.txt_records(&txt_record_name(hostname)).await {
+        Ok(records) if records.iter().any(|record| record.trim() == expected) => {
+            return Some(VerificationMethod::DnsTxt);
+        }
+        Ok(_) => {}
+        Err(e) => tracing::debug!("TXT lookup for {} failed: {}", hostname, e),
+    }
+
+    match probe.fetch(&well_known_url(hostname)).await {
+        Ok(Some(body)) if body.trim() == token => Some(VerificationMethod::WellKnown),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::debug!("Fetching the verification file of {} failed: {}", hostname, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct StubProbe {
+        txt: HashMap<String, Vec<String>>,
+        files: HashMap<String, String>,
+    }
+
+    impl VerificationProbe for StubProbe {
+        fn txt_records<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<String>>> {
+            Box::pin(async move { Ok(self.txt.get(name).cloned().unwrap_or_default()) })
+        }
+
+        fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<String>>> {
+            Box::pin(async move {
+                match self.files.get(url) {
+                    Some(body) => Ok(Some(body.clone())),
+                    None => anyhow::bail!("connection refused"),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_found_in_dns_or_well_known_file() {
+        let mut probe = StubProbe::default();
+        assert_eq!(find_token(&probe, "go.example.com", "abc").await, None);
+
+        probe.txt.insert(
+            "_rustyshort.go.example.com".to_string(),
+            vec!["v=spf1 -all".to_string(), "rustyshort-verification=other".to_string()],
+        );
+        probe.files.insert("http://go.example.com/.well-known/rustyshort-verification.txt".to_string(), "abc\n".to_string());
+        assert_eq!(find_token(&probe, "go.example.com", "abc").await, Some(VerificationMethod::WellKnown));
+
+        probe.txt.get_mut("_rustyshort.go.example.com").unwrap().push("rustyshort-verification=abc".to_string());
+        assert_eq!(find_token(&probe, "go.example.com", "abc").await, Some(VerificationMethod::DnsTxt));
+        assert_eq!(find_token(&probe, "go.example.com", "ab").await, None);
+    }
+}
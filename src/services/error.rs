@@ -0,0 +1,75 @@
+use thiserror::Error;
+
+pub type ServiceResult<T> = std::result::Result<T, ServiceError>;
+
+/// Failures surfaced by the service layer. Each variant maps onto one HTTP status and a
+/// stable machine-readable `code` so clients can tell a bad request from an outage.
+#[derive(Debug, Error)]
+pub enum ServiceError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    Gone(String),
+    #[error("{message}")]
+    RateLimited {
+        message: String,
+        retry_after_secs: Option<u64>,
+    },
+    #[error("{0}")]
+    Unavailable(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl ServiceError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::Validation(_) => "validation_error",
+            ServiceError::Conflict(_) => "conflict",
+            ServiceError::NotFound(_) => "not_found",
+            ServiceError::Forbidden(_) => "forbidden",
+            ServiceError::Gone(_) => "gone",
+            ServiceError::RateLimited { .. } => "rate_limited",
+            ServiceError::Unavailable(_) => "service_unavailable",
+            ServiceError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl From<sqlx::Error> for ServiceError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => {
+                tracing::error!("Database unavailable: {:?}", err);
+                ServiceError::Unavailable("Database is temporarily unavailable".to_string())
+            }
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                ServiceError::Conflict("Resource already exists".to_string())
+            }
+            _ => ServiceError::Internal(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlx_error_mapping() {
+        assert!(matches!(
+            ServiceError::from(sqlx::Error::PoolTimedOut),
+            ServiceError::Unavailable(_)
+        ));
+        assert!(matches!(
+            ServiceError::from(sqlx::Error::RowNotFound),
+            ServiceError::Internal(_)
+        ));
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::{Context, Result};
+use maxminddb::{geoip2, Reader};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Country lookups by client IP in a MaxMind GeoIP2 or GeoLite2 database. Without a
+/// database every lookup comes back empty.
+#[derive(Default)]
+pub struct GeoIp {
+    reader: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    pub fn open(database: Option<&Path>) -> Result<Self> {
+        let reader = database
+            .map(|path| {
+                Reader::open_readfile(path)
+                    .with_context(|| format!("Failed to open GeoIP database {}", path.display()))
+            })
+            .transpose()?;
+        Ok(Self { reader })
+    }
+
+    /// Uppercase ISO 3166-1 alpha-2 code of the country `ip` is located in.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record: geoip2::Country = self.reader.as_ref()?.lookup(ip).ok()?;
+        record
+            .country?
+            .iso_code
+            .filter(|code| code.len() == 2)
+            .map(str::to_ascii_uppercase)
+    }
+}
@@ -0,0 +1,526 @@
+//! Import adapters for links exported from other shorteners. Both keep the original
+//! short key as the alias, so previously shared short URLs keep resolving.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::collections::HashMap;
+
+use crate::{
+    domain::ImportRow,
+    services::link_io::{split_tags, ImportRecord, MAX_RECORD_BYTES},
+    services::{ServiceError, ServiceResult},
+};
+
+/// Columns holding the destination in a Bitly export; one of them must be present.
+pub(crate) const BITLY_URL_COLUMNS: [&str; 3] = ["long_url", "destination_url", "original_url"];
+const BITLY_CUSTOM_KEY_COLUMNS: [&str; 4] = ["custom_bitlinks", "custom_bitlink", "custom_back_half", "back_half"];
+const BITLY_KEY_COLUMNS: [&str; 4] = ["bitlink", "link", "short_url", "id"];
+const BITLY_CREATED_COLUMNS: [&str; 4] = ["created_at", "date_created", "created", "creation_date"];
+const BITLY_CLICK_COLUMNS: [&str; 4] = ["clicks", "total_clicks", "engagements", "click_count"];
+
+/// Column order of `yourls_url`, used when an INSERT does not name its columns.
+const YOURLS_COLUMNS: [&str; 6] = ["keyword", "url", "title", "timestamp", "ip", "clicks"];
+/// Statement text kept while looking for the links table; anything longer is not an
+/// INSERT we care about.
+const MAX_STATEMENT_HEAD_BYTES: usize = 4 * 1024;
+
+fn first<'a>(fields: &'a HashMap<&str, String>, names: &[&str]) -> Option<&'a String> {
+    names.iter().find_map(|name| fields.get(*name))
+}
+
+/// The back-half of a short link, whether given as `https://bit.ly/abc`, `bit.ly/abc`
+/// or just `abc`.
+fn back_half(link: &str) -> Option<String> {
+    let key = link.trim().trim_end_matches('/').rsplit('/').next()?.trim();
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+/// Parses the timestamp styles found in exports; values without an offset are UTC.
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Some(t.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%z", "%Y-%m-%d %H:%M:%S%z"] {
+        if let Ok(t) = DateTime::parse_from_str(value, format) {
+            return Some(t.with_timezone(&Utc));
+        }
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%m/%d/%Y %H:%M:%S", "%m/%d/%Y %H:%M"] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(t.and_utc());
+        }
+    }
+    ["%Y-%m-%d", "%m/%d/%Y"].iter().find_map(|format| {
+        NaiveDate::parse_from_str(value, format)
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|t| t.and_utc())
+    })
+}
+
+fn parse_clicks(value: &str) -> ServiceResult<i64> {
+    value
+        .replace(',', "")
+        .parse::<i64>()
+        .map_err(|_| ServiceError::Validation("Click total must be a whole number".to_string()))
+}
+
+/// Maps one record of a Bitly CSV export. A custom back-half wins over the generated
+/// one, since that is the form people tend to have printed.
+pub(crate) fn bitly_row(fields: &HashMap<&str, String>) -> ServiceResult<ImportRow> {
+    let url = first(fields, &BITLY_URL_COLUMNS)
+        .cloned()
+        .ok_or_else(|| ServiceError::Validation("Long URL is required".to_string()))?;
+
+    // Custom Bitlinks come as a list when a link has several; the first one is kept.
+    let custom = first(fields, &BITLY_CUSTOM_KEY_COLUMNS)
+        .and_then(|v| v.split([',', ';', '|', ' ']).find_map(back_half));
+    let alias = custom.or_else(|| first(fields, &BITLY_KEY_COLUMNS).and_then(|v| back_half(v)));
+
+    let created_at = first(fields, &BITLY_CREATED_COLUMNS)
+        .map(|v| parse_timestamp(v).ok_or_else(|| ServiceError::Validation(format!("Unrecognised date: {}", v))))
+        .transpose()?;
+    let click_count = first(fields, &BITLY_CLICK_COLUMNS).map(|v| parse_clicks(v)).transpose()?;
+    let tags = fields.get("tags").map(|v| split_tags(v)).unwrap_or_default();
+
+    Ok(ImportRow {
+        url,
+        alias,
+        created_at,
+        click_count,
+        tags,
+        ..Default::default()
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comment {
+    Line,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    /// Reading the start of a statement, up to the `(` that follows VALUES.
+    Head,
+    /// Inside the VALUES list of an insert into the links table.
+    Values,
+    /// Inside any other statement; ignored up to its closing `;`.
+    Skip,
+}
+
+/// Streams rows out of a YOURLS MySQL dump. Only INSERTs into the `<prefix>url` table are
+/// read, one value tuple at a time, so extended inserts of any length are fine.
+pub struct YourlsDumpReader {
+    line: usize,
+    section: Section,
+    head: Vec<u8>,
+    columns: Vec<String>,
+    found_table: bool,
+    tuple: Vec<u8>,
+    tuple_line: usize,
+    depth: usize,
+    quote: Option<u8>,
+    escaped: bool,
+    comment: Option<Comment>,
+    prev: u8,
+}
+
+impl Default for YourlsDumpReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl YourlsDumpReader {
+    pub fn new() -> Self {
+        Self {
+            line: 1,
+            section: Section::Head,
+            head: Vec::new(),
+            columns: Vec::new(),
+            found_table: false,
+            tuple: Vec::new(),
+            tuple_line: 0,
+            depth: 0,
+            quote: None,
+            escaped: false,
+            comment: None,
+            prev: 0,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> ServiceResult<Vec<ImportRecord>> {
+        let mut records = Vec::new();
+        for &byte in chunk {
+            if byte == b'\n' {
+                self.line += 1;
+            }
+            self.scan(byte, &mut records)?;
+        }
+        Ok(records)
+    }
+
+    pub fn finish(self) -> ServiceResult<Vec<ImportRecord>> {
+        if self.depth > 0 || self.quote.is_some() {
+            return Err(ServiceError::Validation(format!(
+                "Dump ends inside the row starting on line {}",
+                self.tuple_line
+            )));
+        }
+        if !self.found_table {
+            return Err(ServiceError::Validation(
+                "No INSERT into a YOURLS url table found in the dump".to_string(),
+            ));
+        }
+        Ok(Vec::new())
+    }
+
+    fn scan(&mut self, byte: u8, records: &mut Vec<ImportRecord>) -> ServiceResult<()> {
+        let prev = std::mem::replace(&mut self.prev, byte);
+
+        match self.comment {
+            Some(Comment::Line) => {
+                if byte == b'\n' {
+                    self.comment = None;
+                }
+                return Ok(());
+            }
+            Some(Comment::Block) => {
+                if prev == b'*' && byte == b'/' {
+                    self.comment = None;
+                    self.prev = 0;
+                }
+                return Ok(());
+            }
+            None => {}
+        }
+
+        if let Some(quote) = self.quote {
+            if self.escaped {
+                self.escaped = false;
+            } else if byte == b'\\' && quote != b'`' {
+                self.escaped = true;
+            } else if byte == quote {
+                self.quote = None;
+            }
+            return self.keep(byte);
+        }
+
+        match byte {
+            b'\'' | b'"' | b'`' => {
+                self.quote = Some(byte);
+                self.keep(byte)?;
+            }
+            b'-' if prev == b'-' => {
+                self.unkeep();
+                self.comment = Some(Comment::Line);
+            }
+            b'#' => self.comment = Some(Comment::Line),
+            b'*' if prev == b'/' => {
+                self.unkeep();
+                self.comment = Some(Comment::Block);
+                self.prev = 0;
+            }
+            b';' => {
+                self.section = Section::Head;
+                self.head.clear();
+                self.tuple.clear();
+                self.depth = 0;
+            }
+            _ => self.structure(byte, records)?,
+        }
+
+        Ok(())
+    }
+
+    fn structure(&mut self, byte: u8, records: &mut Vec<ImportRecord>) -> ServiceResult<()> {
+        match self.section {
+            Section::Skip => {}
+            Section::Head if byte == b'(' && ends_with_values(&self.head) => match self.insert_columns() {
+                Some(columns) => {
+                    self.columns = columns;
+                    self.found_table = true;
+                    self.section = Section::Values;
+                    self.open_tuple();
+                }
+                None => self.section = Section::Skip,
+            },
+            Section::Head => {
+                self.head.push(byte);
+                if self.head.len() > MAX_STATEMENT_HEAD_BYTES {
+                    self.head.clear();
+                    self.section = Section::Skip;
+                }
+            }
+            Section::Values => match byte {
+                b'(' if self.depth == 0 => self.open_tuple(),
+                b')' if self.depth == 1 => {
+                    self.depth = 0;
+                    let raw = std::mem::take(&mut self.tuple);
+                    records.push((self.tuple_line, self.parse_tuple(&raw)));
+                }
+                _ if self.depth > 0 => {
+                    match byte {
+                        b'(' => self.depth += 1,
+                        b')' => self.depth -= 1,
+                        _ => {}
+                    }
+                    self.keep(byte)?;
+                }
+                _ => {}
+            },
+        }
+
+        Ok(())
+    }
+
+    fn open_tuple(&mut self) {
+        self.depth = 1;
+        self.tuple.clear();
+        self.tuple_line = self.line;
+    }
+
+    /// Appends a byte to whatever is currently being collected.
+    fn keep(&mut self, byte: u8) -> ServiceResult<()> {
+        match self.section {
+            Section::Head => self.head.push(byte),
+            Section::Values if self.depth > 0 => {
+                self.tuple.push(byte);
+                if self.tuple.len() > MAX_RECORD_BYTES {
+                    return Err(ServiceError::Validation(format!(
+                        "Row starting on line {} exceeds {} bytes",
+                        self.tuple_line, MAX_RECORD_BYTES
+                    )));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Takes back the byte kept just before a comment marker turned out to start.
+    fn unkeep(&mut self) {
+        match self.section {
+            Section::Head => {
+                self.head.pop();
+            }
+            Section::Values if self.depth > 0 => {
+                self.tuple.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// If the statement read so far is an INSERT into the links table, returns the
+    /// columns its values are given in.
+    fn insert_columns(&self) -> Option<Vec<String>> {
+        let head = String::from_utf8_lossy(&self.head);
+        let head = head.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+        let upper = head.to_ascii_uppercase();
+        if !upper.starts_with("INSERT") && !upper.starts_with("REPLACE") {
+            return None;
+        }
+
+        let target = head.get(upper.find(" INTO ")? + 6..upper.len() - "VALUES".len())?.trim();
+        let (table, columns) = match target.find('(') {
+            Some(open) => (&target[..open], Some(target.get(open + 1..target.rfind(')')?)?)),
+            None => (target, None),
+        };
+
+        let table = table.trim().rsplit('.').next()?.trim_matches(['`', '"']);
+        if table != "url" && !table.ends_with("_url") {
+            return None;
+        }
+
+        Some(match columns {
+            Some(columns) => columns
+                .split(',')
+                .map(|c| c.trim().trim_matches(['`', '"']).to_ascii_lowercase())
+                .collect(),
+            None => YOURLS_COLUMNS.iter().map(|c| c.to_string()).collect(),
+        })
+    }
+
+    fn parse_tuple(&self, raw: &[u8]) -> ServiceResult<ImportRow> {
+        let values = split_sql_values(raw)?;
+        let field = |name: &str| {
+            self.columns
+                .iter()
+                .position(|c| c == name)
+                .and_then(|i| values.get(i).cloned().flatten())
+        };
+
+        let url = field("url").ok_or_else(|| ServiceError::Validation("url is required".to_string()))?;
+        let alias = field("keyword").ok_or_else(|| ServiceError::Validation("keyword is required".to_string()))?;
+        // MySQL writes unset DATETIMEs as all zeroes.
+        let created_at = field("timestamp")
+            .filter(|v| !v.starts_with("0000-00-00"))
+            .map(|v| parse_timestamp(&v).ok_or_else(|| ServiceError::Validation(format!("Unrecognised date: {}", v))))
+            .transpose()?;
+        let click_count = field("clicks").map(|v| parse_clicks(&v)).transpose()?;
+
+        Ok(ImportRow {
+            url,
+            alias: Some(alias),
+            created_at,
+            click_count,
+            ..Default::default()
+        })
+    }
+}
+
+fn ends_with_values(head: &[u8]) -> bool {
+    head.trim_ascii_end().to_ascii_uppercase().ends_with(b"VALUES")
+}
+
+/// Splits the inside of one `( ... )` value tuple, unescaping quoted strings. `NULL`
+/// becomes `None`; other bare values are returned as written.
+fn split_sql_values(raw: &[u8]) -> ServiceResult<Vec<Option<String>>> {
+    let malformed = || ServiceError::Validation("Malformed row in SQL dump".to_string());
+    let mut values = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while raw.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+
+        match raw.get(i) {
+            Some(&quote @ (b'\'' | b'"')) => {
+                let mut value = Vec::new();
+                i += 1;
+                loop {
+                    match *raw.get(i).ok_or_else(malformed)? {
+                        b'\\' => {
+                            let escaped = *raw.get(i + 1).ok_or_else(malformed)?;
+                            match escaped {
+                                b'0' => value.push(0),
+                                b'b' => value.push(8),
+                                b'n' => value.push(b'\n'),
+                                b'r' => value.push(b'\r'),
+                                b't' => value.push(b'\t'),
+                                b'Z' => value.push(26),
+                                // MySQL keeps the backslash for these two.
+                                b'%' | b'_' => value.extend([b'\\', escaped]),
+                                other => value.push(other),
+                            }
+                            i += 2;
+                        }
+                        c if c == quote && raw.get(i + 1) == Some(&quote) => {
+                            value.push(quote);
+                            i += 2;
+                        }
+                        c if c == quote => {
+                            i += 1;
+                            break;
+                        }
+                        c => {
+                            value.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                let value = String::from_utf8(value)
+                    .map_err(|_| ServiceError::Validation("Row is not valid UTF-8".to_string()))?;
+                values.push(Some(value));
+            }
+            _ => {
+                let end = raw[i..].iter().position(|&b| b == b',').map_or(raw.len(), |p| i + p);
+                let token = String::from_utf8_lossy(&raw[i..end]).trim().to_string();
+                values.push((!token.eq_ignore_ascii_case("NULL")).then_some(token));
+                i = end;
+            }
+        }
+
+        while raw.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        match raw.get(i) {
+            None => break,
+            Some(b',') => i += 1,
+            Some(_) => return Err(malformed()),
+        }
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitly_rows_keep_their_back_half() {
+        let fields: HashMap<&str, String> = [
+            ("long_url", "https://example.com/a".to_string()),
+            ("bitlink", "https://bit.ly/3xYz".to_string()),
+            ("custom_bitlinks", "bit.ly/spring-sale, bit.ly/other".to_string()),
+            ("created_at", "2021-03-04T05:06:07+0000".to_string()),
+            ("clicks", "1,234".to_string()),
+            ("tags", "spring, print".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let row = bitly_row(&fields).unwrap();
+        assert_eq!(row.alias.as_deref(), Some("spring-sale"));
+        assert_eq!(row.click_count, Some(1234));
+        assert_eq!(row.tags, ["spring", "print"]);
+        assert_eq!(row.created_at.unwrap().to_rfc3339(), "2021-03-04T05:06:07+00:00");
+    }
+
+    #[test]
+    fn test_yourls_dump_rows_are_read_across_chunks() {
+        let dump = "-- MySQL dump\n\
+            /*!40101 SET NAMES utf8mb4 */;\n\
+            CREATE TABLE `yourls_url` (`keyword` varchar(100), `url` text);\n\
+            INSERT INTO `yourls_options` VALUES (1,'version','1.9');\n\
+            INSERT INTO `yourls_url` VALUES ('ozh','http://ozh.org/','It''s (ozh)','2009-09-10 14:14:28','127.0.0.1',12),\n\
+            ('x\\'y','https://example.com/a;b','t','0000-00-00 00:00:00','::1',0),(NULL,'https://example.com/','t','2020-01-01 00:00:00','::1',1);\n";
+
+        let mut reader = YourlsDumpReader::new();
+        let mut records = Vec::new();
+        for chunk in dump.as_bytes().chunks(7) {
+            records.extend(reader.push(chunk).unwrap());
+        }
+        assert!(reader.finish().unwrap().is_empty());
+
+        assert_eq!(records.len(), 3);
+        let first = records[0].1.as_ref().unwrap();
+        assert_eq!(records[0].0, 5);
+        assert_eq!(first.alias.as_deref(), Some("ozh"));
+        assert_eq!(first.url, "http://ozh.org/");
+        assert_eq!(first.click_count, Some(12));
+        assert!(first.created_at.is_some());
+
+        let second = records[1].1.as_ref().unwrap();
+        assert_eq!(records[1].0, 6);
+        assert_eq!(second.alias.as_deref(), Some("x'y"));
+        assert_eq!(second.url, "https://example.com/a;b");
+        assert!(second.created_at.is_none());
+
+        assert!(records[2].1.is_err());
+    }
+
+    #[test]
+    fn test_yourls_inserts_with_column_lists_are_mapped_by_name() {
+        let dump = b"INSERT INTO yourls_url (`url`, `keyword`, `clicks`) VALUES ('https://example.com/', 'abc', 3);";
+        let mut reader = YourlsDumpReader::new();
+        let records = reader.push(dump).unwrap();
+
+        let row = records[0].1.as_ref().unwrap();
+        assert_eq!(row.alias.as_deref(), Some("abc"));
+        assert_eq!(row.click_count, Some(3));
+    }
+
+    #[test]
+    fn test_malformed_insert_heads_are_skipped() {
+        for dump in [
+            &b"INSERT INTO yourls_url) x ( VALUES ('https://example.com/', 'abc', 3);"[..],
+            b"INSERT INTO VALUES ('https://example.com/', 'abc', 3);",
+        ] {
+            let mut reader = YourlsDumpReader::new();
+            assert!(reader.push(dump).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Wire formats for bulk import and export of links.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
+
+use crate::{
+    domain::{ExportRecord, ImportRow, Utm},
+    services::legacy_import::{self, YourlsDumpReader},
+    services::{ServiceError, ServiceResult},
+};
+
+/// Longest single record accepted in an import, so a file without line breaks cannot
+/// exhaust memory.
+pub(crate) const MAX_RECORD_BYTES: usize = 64 * 1024;
+
+const EXPORT_COLUMNS: [&str; 21] = [
+    "alias",
+    "url",
+    "short_url",
+    "expires_at",
+    "redirect_type",
+    "click_count",
+    "created_at",
+    "folder",
+    "tags",
+    "domain",
+    "password_hash",
+    "max_clicks",
+    "starts_at",
+    "prelaunch_url",
+    "fallback_url",
+    "forward_path",
+    "utm_source",
+    "utm_medium",
+    "utm_campaign",
+    "utm_term",
+    "utm_content",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferFormat {
+    Csv,
+    Ndjson,
+}
+
+impl TransferFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "ndjson" | "jsonl" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime.to_ascii_lowercase().as_str() {
+            "text/csv" => Some(Self::Csv),
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Where an uploaded file comes from. Besides our own format, exports of a couple of
+/// other shorteners can be imported directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    Rustyshort,
+    /// Bitly's CSV link export.
+    Bitly,
+    /// A MySQL dump of a YOURLS install.
+    Yourls,
+}
+
+impl ImportSource {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "rustyshort" => Some(Self::Rustyshort),
+            "bitly" => Some(Self::Bitly),
+            "yourls" => Some(Self::Yourls),
+            _ => None,
+        }
+    }
+}
+
+pub type ImportRecord = (usize, ServiceResult<ImportRow>);
+
+/// Incrementally turns an uploaded body into import rows. Bytes are fed in as they arrive
+/// and every complete record is parsed straight away, tagged with the line it starts on.
+pub enum ImportReader {
+    Delimited(DelimitedReader),
+    YourlsDump(YourlsDumpReader),
+}
+
+impl ImportReader {
+    /// `format` only matters for our own files; Bitly exports are always CSV and YOURLS
+    /// dumps are SQL.
+    pub fn new(source: ImportSource, format: TransferFormat) -> Self {
+        match source {
+            ImportSource::Rustyshort => Self::Delimited(DelimitedReader::new(format, source)),
+            ImportSource::Bitly => Self::Delimited(DelimitedReader::new(TransferFormat::Csv, source)),
+            ImportSource::Yourls => Self::YourlsDump(YourlsDumpReader::new()),
+        }
+    }
+
+    /// Consumes the next chunk of the body. Fails only when the file as a whole is
+    /// unusable; problems with individual rows are returned per record.
+    pub fn push(&mut self, chunk: &[u8]) -> ServiceResult<Vec<ImportRecord>> {
+        match self {
+            Self::Delimited(reader) => reader.push(chunk),
+            Self::YourlsDump(reader) => reader.push(chunk),
+        }
+    }
+
+    /// Parses whatever is left once the body has ended.
+    pub fn finish(self) -> ServiceResult<Vec<ImportRecord>> {
+        match self {
+            Self::Delimited(reader) => Ok(reader.finish()?.into_iter().collect()),
+            Self::YourlsDump(reader) => reader.finish(),
+        }
+    }
+}
+
+/// Reader for line-based files: CSV with a header row, or NDJSON.
+pub struct DelimitedReader {
+    format: TransferFormat,
+    source: ImportSource,
+    buf: Vec<u8>,
+    // How far into `buf` has been scanned for a record boundary, and whether that point
+    // is inside a quoted CSV field.
+    scanned: usize,
+    in_quotes: bool,
+    line: usize,
+    header: Option<Vec<String>>,
+}
+
+impl DelimitedReader {
+    fn new(format: TransferFormat, source: ImportSource) -> Self {
+        Self {
+            format,
+            source,
+            buf: Vec::new(),
+            scanned: 0,
+            in_quotes: false,
+            line: 1,
+            header: None,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> ServiceResult<Vec<ImportRecord>> {
+        self.buf.extend_from_slice(chunk);
+        let mut records = Vec::new();
+        let mut start = 0;
+
+        while self.scanned < self.buf.len() {
+            let byte = self.buf[self.scanned];
+            self.scanned += 1;
+            match byte {
+                b'"' if self.format == TransferFormat::Csv => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => {
+                    let raw = self.buf[start..self.scanned - 1].to_vec();
+                    start = self.scanned;
+                    if let Some(record) = self.parse(raw)? {
+                        records.push(record);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.buf.drain(..start);
+        self.scanned -= start;
+        if self.buf.len() > MAX_RECORD_BYTES {
+            return Err(ServiceError::Validation(format!(
+                "Record starting on line {} exceeds {} bytes",
+                self.line, MAX_RECORD_BYTES
+            )));
+        }
+
+        Ok(records)
+    }
+
+    fn finish(mut self) -> ServiceResult<Option<ImportRecord>> {
+        let raw = std::mem::take(&mut self.buf);
+        if self.format == TransferFormat::Csv && self.header.is_none() && raw.iter().all(u8::is_ascii_whitespace) {
+            return Err(ServiceError::Validation("CSV import is missing its header row".to_string()));
+        }
+        self.parse(raw)
+    }
+
+    fn parse(&mut self, mut raw: Vec<u8>) -> ServiceResult<Option<ImportRecord>> {
+        let line = self.line;
+        self.line += 1 + raw.iter().filter(|&&b| b == b'\n').count();
+
+        if raw.last() == Some(&b'\r') {
+            raw.pop();
+        }
+        if line == 1 && raw.starts_with("\u{feff}".as_bytes()) {
+            raw.drain(..3);
+        }
+        if raw.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+
+        let text = match String::from_utf8(raw) {
+            Ok(text) => text,
+            Err(_) => {
+                return Ok(Some((line, Err(ServiceError::Validation("Record is not valid UTF-8".to_string())))));
+            }
+        };
+
+        if self.format == TransferFormat::Ndjson {
+            return Ok(Some((line, parse_json_row(&text))));
+        }
+
+        let Some(ref header) = self.header else {
+            let header: Vec<String> = split_csv_record(&text)
+                .iter()
+                .map(|name| normalize_column(name))
+                .collect();
+            let required = match self.source {
+                ImportSource::Bitly => legacy_import::BITLY_URL_COLUMNS.as_slice(),
+                _ => &["url"],
+            };
+            if !header.iter().any(|name| required.contains(&name.as_str())) {
+                return Err(ServiceError::Validation(format!(
+                    "CSV header must include a {} column",
+                    required.join(" or ")
+                )));
+            }
+            self.header = Some(header);
+            return Ok(None);
+        };
+
+        let fields = csv_fields(header, &text);
+        let row = match self.source {
+            ImportSource::Bitly => legacy_import::bitly_row(&fields),
+            _ => parse_csv_row(&fields),
+        };
+        Ok(Some((line, row)))
+    }
+}
+
+/// Header names are matched case-insensitively, with spaces and hyphens read as `_`.
+fn normalize_column(name: &str) -> String {
+    name.trim().to_ascii_lowercase().replace([' ', '-'], "_")
+}
+
+/// Pairs the non-empty values of a CSV record with their column names.
+fn csv_fields<'a>(header: &'a [String], text: &str) -> HashMap<&'a str, String> {
+    header
+        .iter()
+        .map(String::as_str)
+        .zip(split_csv_record(text))
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(name, value)| (name, value.trim().to_string()))
+        .collect()
+}
+
+fn parse_json_row(text: &str) -> ServiceResult<ImportRow> {
+    serde_json::from_str(text).map_err(|e| ServiceError::Validation(format!("Invalid JSON: {}", e)))
+}
+
+fn parse_csv_row(fields: &HashMap<&str, String>) -> ServiceResult<ImportRow> {
+    let url = fields
+        .get("url")
+        .cloned()
+        .ok_or_else(|| ServiceError::Validation("url is required".to_string()))?;
+
+    let parse_time = |name: &str| {
+        fields
+            .get(name)
+            .map(|v| DateTime::parse_from_rfc3339(v).map(|t| t.with_timezone(&Utc)))
+            .transpose()
+            .map_err(|_| ServiceError::Validation(format!("{} must be an RFC 3339 timestamp", name)))
+    };
+
+    let expires_in = fields
+        .get("expires_in")
+        .map(|v| v.parse::<i64>())
+        .transpose()
+        .map_err(|_| ServiceError::Validation("expires_in must be a whole number of seconds".to_string()))?;
+
+    let redirect_type = fields
+        .get("redirect_type")
+        .map(|v| v.parse::<u16>())
+        .transpose()
+        .map_err(|_| ServiceError::Validation("redirect_type must be an HTTP status code".to_string()))?;
+
+    let whole_number = |name: &str| {
+        fields
+            .get(name)
+            .map(|v| v.parse::<i64>())
+            .transpose()
+            .map_err(|_| ServiceError::Validation(format!("{} must be a whole number", name)))
+    };
+
+    let forward_path = fields
+        .get("forward_path")
+        .map(|v| v.parse::<bool>())
+        .transpose()
+        .map_err(|_| ServiceError::Validation("forward_path must be true or false".to_string()))?;
+
+    let utm = Utm {
+        source: fields.get("utm_source").cloned(),
+        medium: fields.get("utm_medium").cloned(),
+        campaign: fields.get("utm_campaign").cloned(),
+        term: fields.get("utm_term").cloned(),
+        content: fields.get("utm_content").cloned(),
+    };
+
+    Ok(ImportRow {
+        url,
+        alias: fields.get("alias").or_else(|| fields.get("custom_alias")).cloned(),
+        expires_in,
+        expires_at: parse_time("expires_at")?,
+        redirect_type,
+        created_at: parse_time("created_at")?,
+        click_count: whole_number("click_count")?,
+        folder: fields.get("folder").cloned(),
+        tags: fields.get("tags").map(|v| split_tags(v)).unwrap_or_default(),
+        domain: fields.get("domain").cloned(),
+        password_hash: fields.get("password_hash").cloned(),
+        max_clicks: whole_number("max_clicks")?,
+        starts_at: parse_time("starts_at")?,
+        prelaunch_url: fields.get("prelaunch_url").cloned(),
+        fallback_url: fields.get("fallback_url").cloned(),
+        forward_path: forward_path.unwrap_or(false),
+        utm: (!utm.is_empty()).then_some(utm),
+    })
+}
+
+/// Tags share one CSV column, separated by commas.
+pub(crate) fn split_tags(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Splits one CSV record (RFC 4180) into its fields.
+fn split_csv_record(text: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The first chunk of an export: the header row for CSV, nothing for NDJSON.
+pub fn export_header(format: TransferFormat) -> String {
+    match format {
+        TransferFormat::Csv => format!("{}\n", EXPORT_COLUMNS.join(",")),
+        TransferFormat::Ndjson => String::new(),
+    }
+}
+
+/// Encodes one link as a newline-terminated CSV record or JSON line.
+pub fn export_line(format: TransferFormat, record: &ExportRecord) -> String {
+    match format {
+        TransferFormat::Ndjson => {
+            let mut line = serde_json::to_string(record).unwrap_or_default();
+            line.push('\n');
+            line
+        }
+        TransferFormat::Csv => {
+            let timestamp = |t: DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::Secs, true);
+            let text = |value: &Option<String>| csv_field(value.as_deref().unwrap_or_default());
+            let utm = record.utm.clone().unwrap_or_default();
+            let fields = [
+                csv_field(&record.alias),
+                csv_field(&record.url),
+                csv_field(&record.short_url),
+                record.expires_at.map(timestamp).unwrap_or_default(),
+                record.redirect_type.to_string(),
+                record.click_count.to_string(),
+                timestamp(record.created_at),
+                text(&record.folder),
+                csv_field(&record.tags.join(",")),
+                text(&record.domain),
+                text(&record.password_hash),
+                record.max_clicks.map(|n| n.to_string()).unwrap_or_default(),
+                record.starts_at.map(timestamp).unwrap_or_default(),
+                text(&record.prelaunch_url),
+                text(&record.fallback_url),
+                record.forward_path.to_string(),
+                text(&utm.source),
+                text(&utm.medium),
+                text(&utm.campaign),
+                text(&utm.term),
+                text(&utm.content),
+            ];
+            format!("{}\n", fields.join(","))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(format: TransferFormat, chunks: &[&str]) -> Vec<ImportRecord> {
+        let mut reader = ImportReader::new(ImportSource::Rustyshort, format);
+        let mut records = Vec::new();
+        for chunk in chunks {
+            records.extend(reader.push(chunk.as_bytes()).unwrap());
+        }
+        records.extend(reader.finish().unwrap());
+        records
+    }
+
+    #[test]
+    fn test_csv_records_survive_chunk_boundaries_and_quoted_newlines() {
+        let records = read_all(
+            TransferFormat::Csv,
+            &["URL,alias,expires_in\r\n\"https://a.example/?q=\"\"", "x\"\"\",x1,60\r\n\"https://b.exa", "\nmple/\",,\n\nnot-a-number"],
+        );
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].0, 2);
+        let first = records[0].1.as_ref().unwrap();
+        assert_eq!(first.url, "https://a.example/?q=\"x\"");
+        assert_eq!(first.alias.as_deref(), Some("x1"));
+        assert_eq!(first.expires_in, Some(60));
+        // The quoted newline keeps the second record on lines 3-4.
+        assert_eq!(records[1].0, 3);
+        assert_eq!(records[1].1.as_ref().unwrap().url, "https://b.exa\nmple/");
+        assert_eq!(records[2].0, 6);
+        assert_eq!(records[2].1.as_ref().unwrap().url, "not-a-number");
+    }
+
+    #[test]
+    fn test_row_errors_are_reported_per_line() {
+        let records = read_all(
+            TransferFormat::Ndjson,
+            &["{\"url\":\"https://a.example\",\"alias\":\"a\"}\n{\"alias\":\"b\"}\nnope\n"],
+        );
+
+        assert_eq!(records.len(), 3);
+        assert!(records[0].1.is_ok());
+        assert_eq!(records[1].0, 2);
+        assert!(records[1].1.is_err());
+        assert_eq!(records[2].0, 3);
+        assert!(records[2].1.is_err());
+
+        let mut reader = ImportReader::new(ImportSource::Rustyshort, TransferFormat::Csv);
+        assert!(reader.push(b"alias,target\n").is_err());
+    }
+
+    #[test]
+    fn test_exported_csv_can_be_read_back() {
+        let record = ExportRecord {
+            alias: "abc".to_string(),
+            url: "https://example.com/?a=1,2&b=\"x\"".to_string(),
+            short_url: "http://localhost:8080/abc".to_string(),
+            expires_at: None,
+            redirect_type: 301,
+            click_count: 5,
+            created_at: Utc::now(),
+            folder: Some("Spring, 2026".to_string()),
+            tags: vec!["promo".to_string(), "print".to_string()],
+            domain: Some("go.example.com".to_string()),
+            password_hash: None,
+            max_clicks: Some(10),
+            starts_at: None,
+            prelaunch_url: None,
+            fallback_url: Some("https://example.com/over".to_string()),
+            forward_path: true,
+            utm: Some(Utm {
+                campaign: Some("spring".to_string()),
+                ..Default::default()
+            }),
+        };
+        let csv = export_header(TransferFormat::Csv) + &export_line(TransferFormat::Csv, &record);
+
+        let records = read_all(TransferFormat::Csv, &[&csv]);
+        let row = records[0].1.as_ref().unwrap();
+        assert_eq!(row.url, record.url);
+        assert_eq!(row.alias.as_deref(), Some("abc"));
+        assert_eq!(row.redirect_type, Some(301));
+        assert_eq!(row.folder, record.folder);
+        assert_eq!(row.tags, record.tags);
+        assert_eq!(row.domain, record.domain);
+        assert_eq!(row.max_clicks, Some(10));
+        assert_eq!(row.fallback_url, record.fallback_url);
+        assert!(row.forward_path);
+        assert_eq!(row.utm, record.utm);
+        assert_eq!(row.password_hash, None);
+    }
+}
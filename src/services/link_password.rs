@@ -0,0 +1,163 @@
+//! Passwords on short links.
+//!
+//! Passwords are stored as Argon2id PHC strings. Hashing and verifying are deliberately
+//! slow, so both run on the blocking thread pool. Wrong guesses are counted per link
+//! and, past a small allowance, further attempts are refused for the rest of the window
+//! whoever sends them.
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use moka::future::Cache;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{ServiceError, ServiceResult};
+
+/// Wrong passwords accepted per key before attempts are refused.
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+/// How long failures are remembered, counted from the first one.
+const ATTEMPT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+pub async fn hash_password(password: String) -> ServiceResult<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e).into())
+    })
+    .await
+    .map_err(anyhow::Error::from)?
+}
+
+pub async fn verify_password(password: String, hash: String) -> ServiceResult<bool> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash)
+            .map_err(|e| anyhow::anyhow!("Stored password hash is invalid: {}", e))?;
+        Ok(Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+    })
+    .await
+    .map_err(anyhow::Error::from)?
+}
+
+/// Whether `hash` is an Argon2 PHC string `verify_password` can check against, as
+/// carried in exports.
+pub fn is_password_hash(hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"))
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    since: Instant,
+}
+
+/// Failed password attempts per link, kept in process like the request rate limiter.
+#[derive(Clone)]
+pub struct AttemptThrottle {
+    failures: Cache<String, Arc<Mutex<Failures>>>,
+    max_failures: u32,
+    window: Duration,
+}
+
+impl Default for AttemptThrottle {
+    fn default() -> Self {
+        Self::new(MAX_FAILED_ATTEMPTS, ATTEMPT_WINDOW)
+    }
+}
+
+impl AttemptThrottle {
+    pub fn new(max_failures: u32, window: Duration) -> Self {
+        let failures = Cache::builder()
+            .max_capacity(100_000)
+            .time_to_live(window)
+            .build();
+
+        Self { failures, max_failures, window }
+    }
+
+    /// Takes an attempt from `key`'s allowance of wrong passwords, refusing it once the
+    /// allowance is used up. The attempt counts as wrong until `reset` is called, so
+    /// guesses sent in parallel cannot all get in before the first failure is known.
+    pub async fn reserve(&self, key: &str) -> ServiceResult<()> {
+        self.reserve_at(key, Instant::now()).await
+    }
+
+    async fn reserve_at(&self, key: &str, now: Instant) -> ServiceResult<()> {
+        let failures = self
+            .failures
+            .get_with(key.to_string(), async move {
+                Arc::new(Mutex::new(Failures { count: 0, since: now }))
+            })
+            .await;
+
+        let mut failures = failures.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = now.saturating_duration_since(failures.since);
+        if elapsed >= self.window {
+            *failures = Failures { count: 0, since: now };
+        } else if failures.count >= self.max_failures {
+            return Err(ServiceError::RateLimited {
+                message: "Too many wrong passwords for this link, please retry later".to_string(),
+                retry_after_secs: Some((self.window - elapsed).as_secs().max(1)),
+            });
+        }
+        failures.count += 1;
+        Ok(())
+    }
+
+    /// Forgets `key`'s attempts once the right password has been given.
+    pub async fn reset(&self, key: &str) {
+        self.failures.invalidate(key).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_passwords_verify_against_their_hash() {
+        let hash = hash_password("open sesame".to_string()).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("open sesame".to_string(), hash.clone()).await.unwrap());
+        assert!(!verify_password("open sesame!".to_string(), hash.clone()).await.unwrap());
+        assert!(is_password_hash(&hash));
+        assert!(!is_password_hash("open sesame"));
+    }
+
+    #[tokio::test]
+    async fn test_throttle_refuses_attempts_until_the_window_passes() {
+        let throttle = AttemptThrottle::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(throttle.reserve_at("docs", start).await.is_ok());
+        assert!(throttle.reserve_at("docs", start).await.is_ok());
+        assert!(matches!(
+            throttle.reserve_at("docs", start + Duration::from_secs(20)).await,
+            Err(ServiceError::RateLimited { retry_after_secs: Some(40), .. })
+        ));
+        assert!(throttle.reserve_at("other", start).await.is_ok());
+        assert!(throttle.reserve_at("docs", start + Duration::from_secs(60)).await.is_ok());
+
+        throttle.reset("other").await;
+        assert!(throttle.reserve_at("other", start).await.is_ok());
+        assert!(throttle.reserve_at("other", start).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_attempts_share_one_allowance() {
+        let throttle = AttemptThrottle::new(5, Duration::from_secs(60));
+        let attempts = (0..50).map(|_| {
+            let throttle = throttle.clone();
+            tokio::spawn(async move { throttle.reserve("docs").await.is_ok() })
+        });
+        let allowed = futures_util::future::join_all(attempts)
+            .await
+            .into_iter()
+            .filter(|allowed| *allowed.as_ref().unwrap())
+            .count();
+        assert_eq!(allowed, 5);
+    }
+}
@@ -1,123 +1,591 @@
-use anyhow::{anyhow, Result};
+use anyhow::anyhow;
 use chrono::{DateTime, Duration, Utc};
+use futures_util::{stream, Stream, TryStreamExt};
+use moka::future::Cache;
 use nanoid::nanoid;
+use std::collections::HashSet;
 use url::Url;
 use uuid::Uuid;
 
 use crate::{
     cache::LinkCache,
-    domain::{CreateLinkRequest, Link, LinkResponse, LinkStats, AnalyticsSummary, ReferrerStats, DeviceBreakdown, CountryStats, BrowserStats, TimeSeriesPoint},
-    repository::LinkRepository,
-    services::AnalyticsService,
+    domain::{
+        AuthContext, CampaignStats, CreateLinkRequest, FolderSettings, UpdateFolderRequest, Utm,
+        ExportRecord, ImportRow, ImportedHistory, LabelSummary, Link, LinkAnalytics, LinkState,
+        LinkFilter, LinkLabels, LinkSort, LinkRevision, LinkVisit, OwnerSettings,
+        OwnerSettingsUpdate, Page, LinkUpdate, NewLink, TrashedLinkResponse, UpdateLinkRequest,
+        UpdateSettingsRequest, REDIRECT_STATUS_CODES, LinkResponse, LinkStats, AnalyticsSummary,
+        ReferrerStats, DeviceBreakdown, CountryStats, BrowserStats, TimeSeriesPoint,
+    },
+    repository::{AnalyticsScope, LinkRepository},
+    services::{AnalyticsData, ClickContext, DomainService, ServiceError, ServiceResult},
+    services::cursor::{
+        decode_click_cursor, decode_link_cursor, encode_click_cursor, encode_link_cursor,
+    },
+    services::link_io::ImportSource,
+    services::link_password::{hash_password, is_password_hash, verify_password, AttemptThrottle},
+    services::path_forwarding::{self, ForwardedPath},
+    services::{destination_template, utm_tagging},
 };
 
 const DEFAULT_KEY_LENGTH: usize = 7;
 const CUSTOM_ALIAS_MAX_LENGTH: usize = 10;
+/// Keys imported from other shorteners only have to fit the `links.key` column.
+const IMPORTED_KEY_MAX_LENGTH: usize = 100;
 const MAX_URL_LENGTH: usize = 2048;
+const MAX_BATCH_SIZE: usize = 1000;
+const EXPORT_PAGE_SIZE: i64 = 500;
+const FOLDER_NAME_MAX_LENGTH: usize = 100;
+const TAG_MAX_LENGTH: usize = 50;
+const MAX_TAGS_PER_LINK: usize = 20;
+const PASSWORD_MAX_LENGTH: usize = 128;
+const UTM_VALUE_MAX_LENGTH: usize = 200;
 
 #[derive(Clone)]
 pub struct LinkService {
     repository: LinkRepository,
     cache: LinkCache,
+    domains: DomainService,
     pub base_url: String,
+    pub default_redirect_type: u16,
+    deleted_link_retention: Duration,
+    /// Off, links never forward paths whatever they ask for.
+    path_forwarding: bool,
+    password_attempts: AttemptThrottle,
+    /// Folder and owner UTM defaults by `(owner, folder)`, looked up on every redirect.
+    utm_defaults: Cache<(Uuid, Option<Uuid>), Utm>,
 }
 
 impl LinkService {
-    pub fn new(repository: LinkRepository, cache: LinkCache, base_url: String) -> Self {
+    pub fn new(
+        repository: LinkRepository,
+        cache: LinkCache,
+        domains: DomainService,
+        base_url: String,
+        default_redirect_type: u16,
+        deleted_link_retention: Duration,
+        path_forwarding: bool,
+    ) -> Self {
         Self {
             repository,
             cache,
+            domains,
             base_url,
+            default_redirect_type,
+            deleted_link_retention,
+            path_forwarding,
+            password_attempts: AttemptThrottle::default(),
+            utm_defaults: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(std::time::Duration::from_secs(60))
+                .build(),
         }
     }
 
-    pub async fn create_link(&self, request: CreateLinkRequest) -> Result<LinkResponse> {
-        self.validate_url(&request.url)?;
+    pub async fn create_link(&self, request: CreateLinkRequest, owner_id: Uuid) -> ServiceResult<LinkResponse> {
+        self.validate_create_request(&request, CUSTOM_ALIAS_MAX_LENGTH)?;
+        let domain_id = self.owned_domain(request.domain.as_deref(), owner_id).await?;
 
-        let key = if let Some(custom_alias) = request.custom_alias {
-            self.validate_custom_alias(&custom_alias)?;
-            if self.repository.exists(&custom_alias).await? {
-                return Err(anyhow!("Custom alias already exists"));
+        let key = if let Some(ref custom_alias) = request.custom_alias {
+            if self.repository.exists(domain_id, custom_alias).await? {
+                return Err(ServiceError::Conflict("Custom alias already exists".to_string()));
             }
-            custom_alias
+            custom_alias.clone()
         } else {
-            self.generate_unique_key().await?
+            self.generate_unique_key(domain_id).await?
+        };
+
+        let mut new_link = self.new_link(key, &request, owner_id);
+        new_link.domain_id = domain_id;
+        new_link.password_hash = self.hash_link_password(request.password.as_deref()).await?;
+        let labels = LinkLabels { folder: new_link.folder.clone(), tags: new_link.tags.clone() };
+        let link = self.repository.create(new_link).await?;
+
+        self.cache.set(link.clone()).await;
+
+        Ok(self.link_to_response(link, labels))
+    }
+
+    /// Creates many links with one multi-row insert. Each item gets its own result; with
+    /// `all_or_nothing` a single failing item prevents every link from being created.
+    pub async fn create_links(
+        &self,
+        requests: Vec<CreateLinkRequest>,
+        owner_id: Uuid,
+        all_or_nothing: bool,
+    ) -> ServiceResult<Vec<ServiceResult<LinkResponse>>> {
+        let items = requests
+            .into_iter()
+            .map(|request| (request, ImportedHistory::default()))
+            .collect();
+        self.insert_links(items, owner_id, all_or_nothing, CUSTOM_ALIAS_MAX_LENGTH).await
+    }
+
+    /// Creates links from an import. Every row brings along its original creation date and
+    /// click total, so an export can be restored as it was; only rows from other
+    /// shorteners may keep the longer keys those hand out.
+    pub async fn import_links(
+        &self,
+        rows: Vec<ImportRow>,
+        owner_id: Uuid,
+        source: ImportSource,
+    ) -> ServiceResult<Vec<ServiceResult<LinkResponse>>> {
+        let items = rows.into_iter().map(ImportRow::into_parts).collect();
+        let alias_max_length = match source {
+            ImportSource::Rustyshort => CUSTOM_ALIAS_MAX_LENGTH,
+            ImportSource::Bitly | ImportSource::Yourls => IMPORTED_KEY_MAX_LENGTH,
+        };
+        self.insert_links(items, owner_id, false, alias_max_length).await
+    }
+
+    async fn insert_links(
+        &self,
+        items: Vec<(CreateLinkRequest, ImportedHistory)>,
+        owner_id: Uuid,
+        all_or_nothing: bool,
+        alias_max_length: usize,
+    ) -> ServiceResult<Vec<ServiceResult<LinkResponse>>> {
+        if items.len() > MAX_BATCH_SIZE {
+            return Err(ServiceError::Validation(format!(
+                "A batch may contain at most {} links",
+                MAX_BATCH_SIZE
+            )));
+        }
+
+        let mut results: Vec<Option<ServiceResult<LinkResponse>>> =
+            items.iter().map(|_| None).collect();
+        // (item index, link to insert, whether its key was generated rather than requested)
+        let mut pending: Vec<(usize, NewLink, bool)> = Vec::new();
+        let mut aliases = HashSet::new();
+
+        for (index, (request, history)) in items.iter().enumerate() {
+            let validated = self
+                .validate_create_request(request, alias_max_length)
+                .and_then(|_| self.validate_history(history));
+            if let Err(e) = validated {
+                results[index] = Some(Err(e));
+                continue;
+            }
+
+            let domain_id = match self.owned_domain(request.domain.as_deref(), owner_id).await {
+                Ok(domain_id) => domain_id,
+                Err(e) => {
+                    results[index] = Some(Err(e));
+                    continue;
+                }
+            };
+            let (key, generated) = match request.custom_alias {
+                Some(ref alias) if !aliases.insert((domain_id, alias.clone())) => {
+                    results[index] = Some(Err(ServiceError::Conflict(
+                        "Custom alias is used more than once in this batch".to_string(),
+                    )));
+                    continue;
+                }
+                Some(ref alias) => (alias.clone(), false),
+                None => (nanoid!(DEFAULT_KEY_LENGTH, &nanoid::alphabet::SAFE), true),
+            };
+            let mut link = self.new_link(key, request, owner_id);
+            link.domain_id = domain_id;
+            link.created_at = history.created_at;
+            link.click_count = history.click_count;
+            link.password_hash = match history.password_hash {
+                Some(ref hash) => Some(hash.clone()),
+                None => match self.hash_link_password(request.password.as_deref()).await {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        results[index] = Some(Err(e));
+                        continue;
+                    }
+                },
+            };
+            pending.push((index, link, generated));
+        }
+
+        let mut aborted = all_or_nothing && results.iter().any(Option::is_some);
+
+        // Generated keys that collide with existing ones are simply re-rolled; a taken
+        // custom alias is a per-item conflict.
+        let mut attempts = 0;
+        while !aborted && !pending.is_empty() && attempts < 10 {
+            attempts += 1;
+
+            let links: Vec<NewLink> = pending.iter().map(|(_, link, _)| link.clone()).collect();
+            let outcome = self.repository.create_batch(&links, all_or_nothing).await?;
+
+            for link in outcome.inserted {
+                if let Some((index, new_link, _)) = pending.iter().find(|(_, l, _)| l.is_same_key(&link)) {
+                    let labels = LinkLabels { folder: new_link.folder.clone(), tags: new_link.tags.clone() };
+                    results[*index] = Some(Ok(self.link_to_response(link, labels)));
+                }
+            }
+
+            if outcome.conflicts.is_empty() {
+                pending.clear();
+                break;
+            }
+
+            let mut retry = Vec::new();
+            for (index, mut link, generated) in pending.drain(..) {
+                if !outcome.conflicts.iter().any(|(domain_id, key)| *domain_id == link.domain_id && *key == link.key) {
+                    // Only reachable when all_or_nothing rolled the batch back.
+                    retry.push((index, link, generated));
+                } else if generated {
+                    link.key = nanoid!(DEFAULT_KEY_LENGTH, &nanoid::alphabet::SAFE);
+                    retry.push((index, link, generated));
+                } else {
+                    results[index] = Some(Err(ServiceError::Conflict(
+                        "Custom alias already exists".to_string(),
+                    )));
+                    aborted |= all_or_nothing;
+                }
+            }
+            pending = retry;
+        }
+
+        for (index, _, _) in pending {
+            results[index] = Some(Err(if aborted {
+                ServiceError::Validation(
+                    "Not created because another link in the batch failed".to_string(),
+                )
+            } else {
+                anyhow!("Failed to generate unique key after 10 attempts").into()
+            }));
+        }
+
+        Ok(results
+            .into_iter()
+            .map(|r| r.unwrap_or_else(|| Err(anyhow!("Batch item was not processed").into())))
+            .collect())
+    }
+
+    pub async fn update_link(
+        &self,
+        domain_id: Option<Uuid>,
+        key: &str,
+        request: UpdateLinkRequest,
+        auth: &AuthContext,
+    ) -> ServiceResult<Option<LinkResponse>> {
+        let Some(current) = self.find_owned_link(domain_id, key, auth).await? else {
+            return Ok(None);
+        };
+
+        if let Some(ref url) = request.url {
+            self.validate_url(url)?;
+        }
+        if let Some(Some(redirect_type)) = request.redirect_type {
+            self.validate_redirect_type(redirect_type)?;
+        }
+        let folder = request.folder.map(|f| f.map(|name| name.trim().to_string()));
+        if let Some(Some(ref name)) = folder {
+            self.validate_folder(name)?;
+        }
+        let tags = request.tags.map(|tags| normalize_tags(&tags));
+        if let Some(ref tags) = tags {
+            self.validate_tags(tags)?;
+        }
+        if let Some(Some(max_clicks)) = request.max_clicks {
+            self.validate_max_clicks(max_clicks)?;
+        }
+        if let Some(Some(ref prelaunch_url)) = request.prelaunch_url {
+            self.validate_url(prelaunch_url)?;
+        }
+        if let Some(Some(ref fallback_url)) = request.fallback_url {
+            self.validate_url(fallback_url)?;
+        }
+        let utm = request.utm.map(|utm| utm.as_ref().map(normalize_utm).unwrap_or_default());
+        if let Some(ref utm) = utm {
+            self.validate_utm(utm)?;
+        }
+        let password_hash = match request.password {
+            Some(Some(password)) => {
+                self.validate_password(&password)?;
+                Some(self.hash_link_password(Some(&password)).await?)
+            }
+            Some(None) => Some(None),
+            None => None,
         };
 
-        let expires_at = request.expires_in.map(|seconds| {
-            Utc::now() + Duration::seconds(seconds)
-        });
+        let expires_at = match (request.expires_in, request.expires_at) {
+            (Some(_), Some(_)) => {
+                return Err(ServiceError::Validation(
+                    "Only one of expires_in and expires_at may be given".to_string(),
+                ));
+            }
+            (Some(seconds), None) => Some(Some(Utc::now() + Duration::seconds(seconds))),
+            (None, expires_at) => expires_at,
+        };
+        let starts_at = request.starts_at.unwrap_or(current.starts_at);
+        self.validate_schedule(starts_at, expires_at.unwrap_or(current.expires_at))?;
+        let prelaunch_url = match request.prelaunch_url {
+            Some(ref prelaunch_url) => prelaunch_url.as_deref(),
+            None => current.prelaunch_url.as_deref(),
+        };
+        self.validate_prelaunch(starts_at, prelaunch_url)?;
 
-        let link = self.repository.create(
-            key.clone(),
-            request.url.clone(),
+        let update = LinkUpdate {
+            original_url: request.url,
             expires_at,
-            request.owner_id,
-        ).await?;
+            redirect_type: request.redirect_type.map(|code| code.map(|c| c as i16)),
+            folder,
+            tags,
+            password_hash,
+            max_clicks: request.max_clicks,
+            starts_at: request.starts_at,
+            prelaunch_url: request.prelaunch_url,
+            fallback_url: request.fallback_url,
+            forward_path: request.forward_path,
+            utm,
+        };
 
-        self.cache.set(key.clone(), link.clone()).await;
+        let link = self.repository.update(current.id, update, auth).await?;
+        // Drop the cached copy right away so redirects don't keep serving the old
+        // destination until the TTL runs out.
+        self.cache.invalidate(domain_id, key).await;
 
-        Ok(self.link_to_response(link))
+        match link {
+            Some(link) => Ok(Some(self.respond(link).await?)),
+            None => Ok(None),
+        }
     }
 
-    pub async fn get_link(&self, key: &str) -> Result<Option<Link>> {
-        if let Some(link) = self.cache.get(key).await {
-            if !link.is_expired() {
-                return Ok(Some(link));
+    pub async fn list_revisions(
+        &self,
+        domain_id: Option<Uuid>,
+        key: &str,
+        limit: i64,
+        auth: &AuthContext,
+    ) -> ServiceResult<Option<Vec<LinkRevision>>> {
+        let Some(link) = self.find_owned_link(domain_id, key, auth).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(self.repository.list_revisions(link.id, limit).await?))
+    }
+
+    /// Undoes a revision by putting back the destination, expiry and redirect type the
+    /// link had before it. The rollback is itself recorded, so it can be undone too.
+    pub async fn restore_revision(
+        &self,
+        domain_id: Option<Uuid>,
+        key: &str,
+        revision_id: Uuid,
+        auth: &AuthContext,
+    ) -> ServiceResult<LinkResponse> {
+        let link = self
+            .find_owned_link(domain_id, key, auth)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))?;
+
+        let revision = self
+            .repository
+            .find_revision(link.id, revision_id)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("Revision not found".to_string()))?;
+
+        let update = LinkUpdate {
+            original_url: Some(revision.old_url),
+            expires_at: Some(revision.old_expires_at),
+            redirect_type: Some(revision.old_redirect_type),
+            ..Default::default()
+        };
+
+        let restored = self
+            .repository
+            .update(link.id, update, auth)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))?;
+        self.cache.invalidate(domain_id, key).await;
+
+        self.respond(restored).await
+    }
+
+    /// Works out where a visitor of `key` on `domain_id` goes: to the link itself while it
+    /// is live or scheduled, to its fallback destination once it has expired or run out of
+    /// clicks. Such links without a fallback are gone, as are deleted ones.
+    pub async fn visit_link(&self, domain_id: Option<Uuid>, key: &str) -> ServiceResult<LinkVisit> {
+        let now = Utc::now();
+        if let Some(link) = self.cache.get(domain_id, key).await {
+            if matches!(link.state_at(now), LinkState::Active | LinkState::Scheduled) {
+                return Ok(LinkVisit::Open(Box::new(link)));
             }
-            self.cache.invalidate(key).await;
+            self.cache.invalidate(domain_id, key).await;
         }
 
-        if let Some(link) = self.repository.find_by_key(key).await? {
-            if !link.is_expired() {
-                self.cache.set(key.to_string(), link.clone()).await;
-                return Ok(Some(link));
+        let link = self
+            .repository
+            .find_by_key(domain_id, key)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))?;
+
+        match link.state_at(now) {
+            LinkState::Active | LinkState::Scheduled => {
+                self.cache.set(link.clone()).await;
+                Ok(LinkVisit::Open(Box::new(link)))
+            }
+            state => Ok(LinkVisit::Fallback(self.fallback_url(&link, state).await?)),
+        }
+    }
+
+    /// Where a visit to `link` redirects to: its destination with placeholders filled from
+    /// `click`, tagged with `utm`. Links that forward paths also get `forwarded` carried
+    /// over; on any other link, or with forwarding switched off, a path after the key
+    /// names no link and a query string is dropped.
+    pub fn destination(
+        &self,
+        link: &Link,
+        click: &ClickContext,
+        utm: &Utm,
+        forwarded: &ForwardedPath,
+    ) -> ServiceResult<String> {
+        let expanded = destination_template::expand(&link.original_url, click);
+        let tagged = utm_tagging::tag(&expanded, utm);
+        if !(link.forward_path && self.path_forwarding) {
+            if forwarded.has_path() {
+                return Err(ServiceError::NotFound("Link not found".to_string()));
+            }
+            return Ok(tagged);
+        }
+
+        path_forwarding::forward(&tagged, forwarded)
+            .ok_or_else(|| ServiceError::NotFound("Link not found".to_string()))
+    }
+
+    /// The UTM parameters a visit to `link` is tagged with: its own, then its folder's
+    /// defaults, then its owner's.
+    pub async fn utm_for(&self, link: &Link) -> ServiceResult<Utm> {
+        let Some(owner_id) = link.owner_id else {
+            return Ok(link.utm.clone());
+        };
+
+        let cache_key = (owner_id, link.folder_id);
+        let defaults = match self.utm_defaults.get(&cache_key).await {
+            Some(defaults) => defaults,
+            None => {
+                let defaults = self.repository.utm_defaults(owner_id, link.folder_id).await?;
+                self.utm_defaults.insert(cache_key, defaults.clone()).await;
+                defaults
             }
+        };
+        Ok(link.utm.clone().or(&defaults))
+    }
+
+    /// Where visitors of a link that can no longer be followed are sent: its own fallback
+    /// URL, or else its owner's default one. Without either the link is gone.
+    pub async fn fallback_url(&self, link: &Link, state: LinkState) -> ServiceResult<String> {
+        let message = match state {
+            LinkState::Deleted => return Err(ServiceError::Gone("Link has been deleted".to_string())),
+            LinkState::Exhausted => "Link has reached its click limit",
+            _ => "Link has expired",
+        };
+
+        let fallback_url = match (&link.fallback_url, link.owner_id) {
+            (Some(fallback_url), _) => Some(fallback_url.clone()),
+            (None, Some(owner_id)) => self
+                .repository
+                .find_owner_settings(owner_id)
+                .await?
+                .and_then(|settings| settings.fallback_url),
+            (None, None) => None,
+        };
+
+        fallback_url.ok_or_else(|| ServiceError::Gone(message.to_string()))
+    }
+
+    /// Checks a visitor's password for a protected link. Wrong guesses count against the
+    /// link's key, and once too many have been made every attempt is refused for a while.
+    pub async fn check_link_password(&self, link: &Link, password: &str) -> ServiceResult<bool> {
+        let Some(ref hash) = link.password_hash else {
+            return Ok(true);
+        };
+
+        let attempts_key = link.id.to_string();
+        self.password_attempts.reserve(&attempts_key).await?;
+        let valid = verify_password(password.to_string(), hash.clone()).await?;
+        if valid {
+            self.password_attempts.reset(&attempts_key).await;
         }
+        Ok(valid)
+    }
 
-        Ok(None)
+    /// Takes one click from a click-limited link's budget before it is followed. Returns
+    /// `false` once the budget is used up.
+    pub async fn claim_click(&self, link: &Link) -> ServiceResult<bool> {
+        match self.repository.claim_click(link.id).await? {
+            Some(link) => {
+                self.cache.set(link).await;
+                Ok(true)
+            }
+            None => {
+                self.cache.invalidate(link.domain_id, &link.key).await;
+                Ok(false)
+            }
+        }
     }
 
-    pub async fn increment_click(&self, key: &str) -> Result<()> {
-        self.repository.increment_click_count(key).await?;
-        self.cache.invalidate(key).await;
+    pub async fn increment_click(&self, link: &Link) -> ServiceResult<()> {
+        self.repository.increment_click_count(link.id).await?;
+        self.cache.invalidate(link.domain_id, &link.key).await;
         Ok(())
     }
 
     pub async fn record_analytics(
         &self,
         link_id: Uuid,
-        referrer: Option<String>,
-        user_agent: Option<String>,
-        ip_hash: Option<String>,
-    ) -> Result<()> {
-        let (browser, os, device_type) = if let Some(ref ua) = user_agent {
-            AnalyticsService::parse_user_agent(ua)
-        } else {
-            (None, None, None)
-        };
-        
-        self.repository.record_analytics(
-            link_id,
-            referrer,
-            user_agent,
-            ip_hash,
-            browser,
-            os,
-            device_type,
-        ).await?;
+        click: ClickContext,
+        campaign: Option<String>,
+    ) -> ServiceResult<()> {
+        self.repository.record_analytics(link_id, AnalyticsData {
+            click_id: click.click_id,
+            referrer: click.referrer,
+            user_agent: click.user_agent,
+            ip_hash: click.ip_hash,
+            browser: click.browser,
+            os: click.os,
+            device_type: click.device_type,
+            country_code: click.country_code,
+            campaign,
+        }).await?;
         Ok(())
     }
     
-    pub async fn get_analytics_summary(&self, key: &str, days: i32) -> Result<Option<AnalyticsSummary>> {
-        if self.repository.find_by_key(key).await?.is_none() {
+    /// Looks up a live link for a management endpoint. Links owned by another tenant are
+    /// reported as missing so callers cannot probe which keys exist; trashed links are
+    /// only reachable through the trash endpoints.
+    pub async fn find_owned_link(&self, domain_id: Option<Uuid>, key: &str, auth: &AuthContext) -> ServiceResult<Option<Link>> {
+        let link = self.repository.find_by_key(domain_id, key).await?;
+        Ok(link.filter(|l| auth.can_access(l) && !l.is_deleted()))
+    }
+
+    pub async fn get_analytics_summary(
+        &self,
+        domain_id: Option<Uuid>,
+        key: &str,
+        days: i32,
+        auth: &AuthContext,
+    ) -> ServiceResult<Option<AnalyticsSummary>> {
+        let Some(link) = self.find_owned_link(domain_id, key, auth).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(self.summarize(&AnalyticsScope::Link(link.id), days).await?))
+    }
+
+    /// Clicks on every live link carrying `tag`, added up. `owner_id` of `None` covers
+    /// the tag across all owners.
+    pub async fn get_tag_analytics(&self, tag: &str, owner_id: Option<Uuid>, days: i32) -> ServiceResult<Option<AnalyticsSummary>> {
+        let tag = tag.trim().to_lowercase();
+        if !self.repository.tag_exists(&tag, owner_id).await? {
             return Ok(None);
         }
+
+        Ok(Some(self.summarize(&AnalyticsScope::Tag { name: &tag, owner_id }, days).await?))
+    }
+
+    async fn summarize(&self, scope: &AnalyticsScope<'_>, days: i32) -> ServiceResult<AnalyticsSummary> {
+        let total_clicks = self.repository.get_total_clicks(scope).await?;
+        let unique_visitors = self.repository.get_unique_visitors(scope).await?;
         
-        let total_clicks = self.repository.get_total_clicks(key).await?;
-        let unique_visitors = self.repository.get_unique_visitors(key).await?;
-        
-        let referrers = self.repository.get_top_referrers(key, 10).await?;
+        let referrers = self.repository.get_top_referrers(scope, 10).await?;
         let top_referrers = referrers.into_iter().map(|(domain, count)| {
             let percentage = if total_clicks > 0 {
                 (count as f64 / total_clicks as f64) * 100.0
@@ -131,7 +599,7 @@ impl LinkService {
             }
         }).collect();
         
-        let devices = self.repository.get_device_breakdown(key).await?;
+        let devices = self.repository.get_device_breakdown(scope).await?;
         let mut device_breakdown = DeviceBreakdown {
             desktop: 0,
             mobile: 0,
@@ -149,7 +617,7 @@ impl LinkService {
             }
         }
         
-        let countries = self.repository.get_country_stats(key, 10).await?;
+        let countries = self.repository.get_country_stats(scope, 10).await?;
         let geographic_distribution = countries.into_iter().map(|(country_code, count)| {
             let percentage = if total_clicks > 0 {
                 (count as f64 / total_clicks as f64) * 100.0
@@ -163,7 +631,7 @@ impl LinkService {
             }
         }).collect();
         
-        let browsers = self.repository.get_browser_stats(key, 10).await?;
+        let browsers = self.repository.get_browser_stats(scope, 10).await?;
         let browser_stats = browsers.into_iter().map(|(browser, count)| {
             let percentage = if total_clicks > 0 {
                 (count as f64 / total_clicks as f64) * 100.0
@@ -177,7 +645,7 @@ impl LinkService {
             }
         }).collect();
         
-        let time_data = self.repository.get_time_series(key, days).await?;
+        let time_data = self.repository.get_time_series(scope, days).await?;
         let time_series = time_data.into_iter().map(|(date, clicks, unique)| {
             TimeSeriesPoint {
                 date,
@@ -186,7 +654,7 @@ impl LinkService {
             }
         }).collect();
         
-        Ok(Some(AnalyticsSummary {
+        Ok(AnalyticsSummary {
             total_clicks,
             unique_visitors,
             top_referrers,
@@ -194,88 +662,622 @@ impl LinkService {
             geographic_distribution,
             browser_stats,
             time_series,
-        }))
+        })
     }
 
-    pub async fn get_stats(&self, key: &str) -> Result<Option<LinkStats>> {
-        let link = self.repository.find_by_key(key).await?;
-        
+    pub async fn get_stats(&self, domain_id: Option<Uuid>, key: &str, auth: &AuthContext) -> ServiceResult<Option<LinkStats>> {
+        let link = self.find_owned_link(domain_id, key, auth).await?;
+
         Ok(link.map(|l| LinkStats {
             key: l.key,
             original_url: l.original_url,
             click_count: l.click_count,
             created_at: l.created_at,
             expires_at: l.expires_at,
+            max_clicks: l.max_clicks,
+            starts_at: l.starts_at,
         }))
     }
 
-    pub async fn delete_link(&self, key: &str) -> Result<bool> {
-        let deleted = self.repository.delete(key).await?;
+    pub async fn delete_link(&self, domain_id: Option<Uuid>, key: &str, auth: &AuthContext) -> ServiceResult<bool> {
+        let Some(link) = self.find_owned_link(domain_id, key, auth).await? else {
+            return Ok(false);
+        };
+
+        let deleted = self.repository.soft_delete(link.id).await?;
         if deleted {
-            self.cache.invalidate(key).await;
+            self.cache.invalidate(domain_id, key).await;
         }
         Ok(deleted)
     }
 
-    pub async fn list_links(&self, limit: i64, offset: i64) -> Result<Vec<LinkResponse>> {
-        let links = self.repository.list(limit, offset).await?;
-        Ok(links.into_iter().map(|l| self.link_to_response(l)).collect())
+    pub async fn restore_link(&self, domain_id: Option<Uuid>, key: &str, auth: &AuthContext) -> ServiceResult<LinkResponse> {
+        let trashed = self
+            .repository
+            .find_by_key(domain_id, key)
+            .await?
+            .filter(|l| auth.can_access(l) && l.is_deleted())
+            .ok_or_else(|| ServiceError::NotFound("Link not found in trash".to_string()))?;
+
+        let link = self
+            .repository
+            .restore(trashed.id)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("Link not found in trash".to_string()))?;
+        self.cache.invalidate(domain_id, key).await;
+
+        self.respond(link).await
+    }
+
+    /// One page of live links. Paging continues from `cursor` when given; the sort then
+    /// defaults to the one the cursor was issued for.
+    pub async fn list_links(
+        &self,
+        filter: &LinkFilter,
+        sort: Option<LinkSort>,
+        cursor: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> ServiceResult<Page<LinkResponse>> {
+        let after = cursor.map(decode_link_cursor).transpose()?;
+        let sort = match (&after, sort) {
+            (Some(after), Some(sort)) if after.sort() != sort => {
+                return Err(ServiceError::Validation(
+                    "Cursor was issued for a different sort order".to_string(),
+                ));
+            }
+            (Some(_), _) if offset != 0 => {
+                return Err(ServiceError::Validation(
+                    "offset cannot be combined with cursor".to_string(),
+                ));
+            }
+            (Some(after), _) => after.sort(),
+            (None, sort) => sort.unwrap_or_default(),
+        };
+
+        // One extra row tells whether there is a next page.
+        let mut links = self
+            .repository
+            .list(filter, Some(sort), after.as_ref(), false, limit + 1, offset)
+            .await?;
+        let next_cursor = next_page(&mut links, limit).map(|last| encode_link_cursor(&sort.cursor_after(last)));
+
+        Ok(Page { items: self.respond_all(links).await?, next_cursor })
+    }
+
+    /// Clicks on an owned link, newest first, continuing from `cursor` when given.
+    pub async fn get_detailed_analytics(
+        &self,
+        domain_id: Option<Uuid>,
+        key: &str,
+        cursor: Option<&str>,
+        limit: i64,
+        auth: &AuthContext,
+    ) -> ServiceResult<Option<Page<LinkAnalytics>>> {
+        let after = cursor.map(decode_click_cursor).transpose()?;
+        let Some(link) = self.find_owned_link(domain_id, key, auth).await? else {
+            return Ok(None);
+        };
+
+        let mut clicks = self.repository.get_analytics(link.id, after, limit + 1).await?;
+        let next_cursor = next_page(&mut clicks, limit).map(|last| encode_click_cursor(last.clicked_at, last.id));
+
+        Ok(Some(Page { items: clicks, next_cursor }))
+    }
+
+    pub async fn list_trash(
+        &self,
+        filter: &LinkFilter,
+        sort: Option<LinkSort>,
+        limit: i64,
+        offset: i64,
+    ) -> ServiceResult<Vec<TrashedLinkResponse>> {
+        let links = self.repository.list(filter, sort, None, true, limit, offset).await?;
+        let deleted: Vec<_> = links.iter().map(|l| l.deleted_at).collect();
+        Ok(self
+            .respond_all(links)
+            .await?
+            .into_iter()
+            .zip(deleted)
+            .filter_map(|(link, deleted_at)| {
+                let deleted_at = deleted_at?;
+                Some(TrashedLinkResponse {
+                    link,
+                    deleted_at,
+                    purge_after: deleted_at + self.deleted_link_retention,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn list_tags(&self, owner_id: Option<Uuid>) -> ServiceResult<Vec<LabelSummary>> {
+        Ok(self.repository.list_tags(owner_id).await?)
+    }
+
+    pub async fn list_folders(&self, owner_id: Option<Uuid>) -> ServiceResult<Vec<LabelSummary>> {
+        Ok(self.repository.list_folders(owner_id).await?)
+    }
+
+    /// Sets the UTM defaults of one of the owner's folders, creating the folder if need be.
+    pub async fn update_folder(&self, owner_id: Uuid, name: &str, request: UpdateFolderRequest) -> ServiceResult<FolderSettings> {
+        let name = name.trim();
+        self.validate_folder(name)?;
+        let utm = request.utm.flatten().as_ref().map(normalize_utm).unwrap_or_default();
+        self.validate_utm(&utm)?;
+
+        let folder = self.repository.update_folder_utm(owner_id, name, &utm).await?;
+        self.utm_defaults.invalidate_all();
+        Ok(folder)
+    }
+
+    pub async fn get_settings(&self, owner_id: Uuid) -> ServiceResult<OwnerSettings> {
+        let settings = self.repository.find_owner_settings(owner_id).await?;
+        Ok(settings.unwrap_or(OwnerSettings {
+            owner_id,
+            fallback_url: None,
+            utm: Utm::default(),
+            updated_at: None,
+        }))
+    }
+
+    pub async fn update_settings(&self, owner_id: Uuid, request: UpdateSettingsRequest) -> ServiceResult<OwnerSettings> {
+        if let Some(Some(ref fallback_url)) = request.fallback_url {
+            self.validate_url(fallback_url)?;
+        }
+        let utm = request.utm.map(|utm| utm.as_ref().map(normalize_utm).unwrap_or_default());
+        if let Some(ref utm) = utm {
+            self.validate_utm(utm)?;
+        }
+
+        let update = OwnerSettingsUpdate {
+            fallback_url: request.fallback_url,
+            utm,
+        };
+        let settings = self.repository.update_owner_settings(owner_id, update).await?;
+        self.utm_defaults.invalidate_all();
+        Ok(settings)
     }
 
-    async fn generate_unique_key(&self) -> Result<String> {
+    /// Clicks by the `utm_campaign` they were tagged with over the last `days` days.
+    pub async fn list_campaigns(&self, owner_id: Option<Uuid>, days: i32) -> ServiceResult<Vec<CampaignStats>> {
+        Ok(self.repository.campaign_stats(owner_id, days).await?)
+    }
+
+    /// Permanently removes links whose retention period in the trash has run out.
+    pub async fn purge_deleted(&self) -> ServiceResult<u64> {
+        let purged = self
+            .repository
+            .purge_deleted(Utc::now() - self.deleted_link_retention)
+            .await?;
+        Ok(purged)
+    }
+
+    /// Streams every live link of `owner_id` (all owners when `None`), reading the table a
+    /// page at a time so large inventories are never held in memory at once.
+    pub fn export_links(
+        &self,
+        owner_id: Option<Uuid>,
+    ) -> impl Stream<Item = ServiceResult<ExportRecord>> + Send + 'static {
+        let service = self.clone();
+        // `None` once the last page has been read; otherwise the position to continue after.
+        stream::try_unfold(Some(None), move |after| {
+            let service = service.clone();
+            async move {
+                let Some(after) = after else {
+                    return Ok::<_, ServiceError>(None);
+                };
+                let links = service.repository.list_after(owner_id, after, EXPORT_PAGE_SIZE).await?;
+                let next = match links.last() {
+                    Some(last) if links.len() as i64 == EXPORT_PAGE_SIZE => Some(Some((last.created_at, last.id))),
+                    _ => None,
+                };
+                let ids: Vec<Uuid> = links.iter().map(|l| l.id).collect();
+                let mut labels = service.repository.labels(&ids).await?;
+                let records: Vec<ServiceResult<ExportRecord>> = links
+                    .into_iter()
+                    .map(|link| {
+                        let labels = labels.remove(&link.id).unwrap_or_default();
+                        Ok(service.export_record(link, labels))
+                    })
+                    .collect();
+                Ok(Some((stream::iter(records), next)))
+            }
+        })
+        .try_flatten()
+    }
+
+    async fn generate_unique_key(&self, domain_id: Option<Uuid>) -> ServiceResult<String> {
         for _ in 0..10 {
             let key = nanoid!(DEFAULT_KEY_LENGTH, &nanoid::alphabet::SAFE);
-            if !self.repository.exists(&key).await? {
+            if !self.repository.exists(domain_id, &key).await? {
                 return Ok(key);
             }
         }
-        Err(anyhow!("Failed to generate unique key after 10 attempts"))
+        Err(anyhow!("Failed to generate unique key after 10 attempts").into())
+    }
+
+    fn validate_create_request(&self, request: &CreateLinkRequest, alias_max_length: usize) -> ServiceResult<()> {
+        self.validate_url(&request.url)?;
+        if let Some(redirect_type) = request.redirect_type {
+            self.validate_redirect_type(redirect_type)?;
+        }
+        if let Some(ref custom_alias) = request.custom_alias {
+            self.validate_custom_alias(custom_alias, alias_max_length)?;
+        }
+        if request.expires_in.is_some() && request.expires_at.is_some() {
+            return Err(ServiceError::Validation(
+                "Only one of expires_in and expires_at may be given".to_string(),
+            ));
+        }
+        if let Some(ref folder) = request.folder {
+            self.validate_folder(folder.trim())?;
+        }
+        self.validate_tags(&normalize_tags(&request.tags))?;
+        if let Some(ref password) = request.password {
+            self.validate_password(password)?;
+        }
+        if let Some(max_clicks) = request.max_clicks {
+            self.validate_max_clicks(max_clicks)?;
+        }
+        if let Some(ref prelaunch_url) = request.prelaunch_url {
+            self.validate_url(prelaunch_url)?;
+        }
+        self.validate_prelaunch(request.starts_at, request.prelaunch_url.as_deref())?;
+        if let Some(ref fallback_url) = request.fallback_url {
+            self.validate_url(fallback_url)?;
+        }
+        if let Some(ref utm) = request.utm {
+            self.validate_utm(&normalize_utm(utm))?;
+        }
+        let expires_at = request
+            .expires_in
+            .map(|seconds| Utc::now() + Duration::seconds(seconds))
+            .or(request.expires_at);
+        self.validate_schedule(request.starts_at, expires_at)?;
+
+        Ok(())
+    }
+
+    fn validate_schedule(
+        &self,
+        starts_at: Option<DateTime<Utc>>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> ServiceResult<()> {
+        if let (Some(starts_at), Some(expires_at)) = (starts_at, expires_at) {
+            if starts_at >= expires_at {
+                return Err(ServiceError::Validation(
+                    "starts_at must be before the link expires".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A pre-launch destination only makes sense for a link that has a launch time.
+    fn validate_prelaunch(
+        &self,
+        starts_at: Option<DateTime<Utc>>,
+        prelaunch_url: Option<&str>,
+    ) -> ServiceResult<()> {
+        if prelaunch_url.is_some() && starts_at.is_none() {
+            return Err(ServiceError::Validation(
+                "prelaunch_url requires starts_at".to_string(),
+            ));
+        }
+
+        Ok(())
     }
 
-    fn validate_url(&self, url_str: &str) -> Result<()> {
+    fn validate_history(&self, history: &ImportedHistory) -> ServiceResult<()> {
+        if history.click_count < 0 {
+            return Err(ServiceError::Validation("click_count cannot be negative".to_string()));
+        }
+        if history.created_at.is_some_and(|created_at| created_at > Utc::now()) {
+            return Err(ServiceError::Validation("created_at cannot be in the future".to_string()));
+        }
+        if history.password_hash.as_deref().is_some_and(|hash| !is_password_hash(hash)) {
+            return Err(ServiceError::Validation(
+                "password_hash must be an Argon2 PHC string".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn new_link(&self, key: String, request: &CreateLinkRequest, owner_id: Uuid) -> NewLink {
+        NewLink {
+            key,
+            original_url: request.url.clone(),
+            expires_at: request
+                .expires_in
+                .map(|seconds| Utc::now() + Duration::seconds(seconds))
+                .or(request.expires_at),
+            owner_id: Some(owner_id),
+            redirect_type: request.redirect_type.map(|code| code as i16),
+            created_at: None,
+            click_count: 0,
+            folder: request.folder.as_ref().map(|f| f.trim().to_string()),
+            tags: normalize_tags(&request.tags),
+            password_hash: None,
+            max_clicks: request.max_clicks,
+            starts_at: request.starts_at,
+            prelaunch_url: request.prelaunch_url.clone(),
+            fallback_url: request.fallback_url.clone(),
+            forward_path: request.forward_path,
+            utm: request.utm.as_ref().map(normalize_utm).unwrap_or_default(),
+            domain_id: None,
+        }
+    }
+
+    /// Resolves the custom domain a new link is requested on, which its owner must have
+    /// registered and verified.
+    async fn owned_domain(&self, hostname: Option<&str>, owner_id: Uuid) -> ServiceResult<Option<Uuid>> {
+        match self.domains.resolve(hostname).await? {
+            Some(domain) if domain.owner_id != owner_id => {
+                Err(ServiceError::NotFound("Domain not found".to_string()))
+            }
+            Some(domain) if !domain.is_verified() => {
+                Err(ServiceError::Validation("domain is pending verification".to_string()))
+            }
+            domain => Ok(domain.map(|d| d.id)),
+        }
+    }
+
+    async fn hash_link_password(&self, password: Option<&str>) -> ServiceResult<Option<String>> {
+        match password {
+            Some(password) => Ok(Some(hash_password(password.to_string()).await?)),
+            None => Ok(None),
+        }
+    }
+
+    fn validate_url(&self, url_str: &str) -> ServiceResult<()> {
         if url_str.len() > MAX_URL_LENGTH {
-            return Err(anyhow!("URL exceeds maximum length of {} characters", MAX_URL_LENGTH));
+            return Err(ServiceError::Validation(format!(
+                "URL exceeds maximum length of {} characters",
+                MAX_URL_LENGTH
+            )));
         }
 
         let url = Url::parse(url_str)
-            .map_err(|_| anyhow!("Invalid URL format"))?;
+            .map_err(|_| ServiceError::Validation("Invalid URL format".to_string()))?;
 
         if !matches!(url.scheme(), "http" | "https") {
-            return Err(anyhow!("URL must use HTTP or HTTPS protocol"));
+            return Err(ServiceError::Validation("URL must use HTTP or HTTPS protocol".to_string()));
         }
 
         if url.host_str().is_none() {
-            return Err(anyhow!("URL must have a valid host"));
+            return Err(ServiceError::Validation("URL must have a valid host".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn validate_utm(&self, utm: &Utm) -> ServiceResult<()> {
+        for (name, value) in utm.fields() {
+            if value.is_some_and(|v| v.chars().count() > UTM_VALUE_MAX_LENGTH) {
+                return Err(ServiceError::Validation(format!(
+                    "{} exceeds maximum length of {}",
+                    name, UTM_VALUE_MAX_LENGTH
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_folder(&self, name: &str) -> ServiceResult<()> {
+        if name.is_empty() {
+            return Err(ServiceError::Validation("Folder name cannot be empty".to_string()));
+        }
+        if name.chars().count() > FOLDER_NAME_MAX_LENGTH {
+            return Err(ServiceError::Validation(format!(
+                "Folder name exceeds maximum length of {}",
+                FOLDER_NAME_MAX_LENGTH
+            )));
         }
 
         Ok(())
     }
 
-    fn validate_custom_alias(&self, alias: &str) -> Result<()> {
+    fn validate_tags(&self, tags: &[String]) -> ServiceResult<()> {
+        if tags.len() > MAX_TAGS_PER_LINK {
+            return Err(ServiceError::Validation(format!(
+                "A link may have at most {} tags",
+                MAX_TAGS_PER_LINK
+            )));
+        }
+        for tag in tags {
+            if tag.is_empty() {
+                return Err(ServiceError::Validation("Tags cannot be empty".to_string()));
+            }
+            if tag.chars().count() > TAG_MAX_LENGTH {
+                return Err(ServiceError::Validation(format!(
+                    "Tag exceeds maximum length of {}",
+                    TAG_MAX_LENGTH
+                )));
+            }
+            // Commas separate tags in CSV imports and exports.
+            if tag.contains(',') {
+                return Err(ServiceError::Validation("Tags cannot contain commas".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_password(&self, password: &str) -> ServiceResult<()> {
+        if password.is_empty() {
+            return Err(ServiceError::Validation("Password cannot be empty".to_string()));
+        }
+        if password.chars().count() > PASSWORD_MAX_LENGTH {
+            return Err(ServiceError::Validation(format!(
+                "Password exceeds maximum length of {}",
+                PASSWORD_MAX_LENGTH
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn validate_max_clicks(&self, max_clicks: i64) -> ServiceResult<()> {
+        if max_clicks < 1 {
+            return Err(ServiceError::Validation("max_clicks must be at least 1".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn validate_custom_alias(&self, alias: &str, max_length: usize) -> ServiceResult<()> {
         if alias.is_empty() {
-            return Err(anyhow!("Custom alias cannot be empty"));
+            return Err(ServiceError::Validation("Custom alias cannot be empty".to_string()));
         }
 
-        if alias.len() > CUSTOM_ALIAS_MAX_LENGTH {
-            return Err(anyhow!("Custom alias exceeds maximum length of {}", CUSTOM_ALIAS_MAX_LENGTH));
+        if alias.len() > max_length {
+            return Err(ServiceError::Validation(format!(
+                "Custom alias exceeds maximum length of {}",
+                max_length
+            )));
         }
 
         if !alias.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
-            return Err(anyhow!("Custom alias can only contain alphanumeric characters, hyphens, and underscores"));
+            return Err(ServiceError::Validation(
+                "Custom alias can only contain alphanumeric characters, hyphens, and underscores"
+                    .to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn validate_redirect_type(&self, redirect_type: u16) -> ServiceResult<()> {
+        if !REDIRECT_STATUS_CODES.contains(&redirect_type) {
+            return Err(ServiceError::Validation(format!(
+                "Redirect type must be one of {:?}",
+                REDIRECT_STATUS_CODES
+            )));
         }
 
         Ok(())
     }
 
-    fn link_to_response(&self, link: Link) -> LinkResponse {
+    /// The status code a link redirects with: its own setting, else the deployment default.
+    pub fn effective_redirect_type(&self, link: &Link) -> u16 {
+        link.redirect_type
+            .map(|code| code as u16)
+            .unwrap_or(self.default_redirect_type)
+    }
+
+    fn export_record(&self, link: Link, labels: LinkLabels) -> ExportRecord {
+        ExportRecord {
+            redirect_type: self.effective_redirect_type(&link),
+            short_url: self.short_url(link.domain.as_deref(), &link.key),
+            alias: link.key,
+            url: link.original_url,
+            expires_at: link.expires_at,
+            click_count: link.click_count,
+            created_at: link.created_at,
+            folder: labels.folder,
+            tags: labels.tags,
+            domain: link.domain,
+            password_hash: link.password_hash,
+            max_clicks: link.max_clicks,
+            starts_at: link.starts_at,
+            prelaunch_url: link.prelaunch_url,
+            fallback_url: link.fallback_url,
+            forward_path: link.forward_path,
+            utm: Some(link.utm).filter(|utm| !utm.is_empty()),
+        }
+    }
+
+    async fn respond(&self, link: Link) -> ServiceResult<LinkResponse> {
+        let mut labels = self.repository.labels(&[link.id]).await?;
+        let labels = labels.remove(&link.id).unwrap_or_default();
+        Ok(self.link_to_response(link, labels))
+    }
+
+    /// Builds responses for `links`, looking up their folders and tags in one query.
+    async fn respond_all(&self, links: Vec<Link>) -> ServiceResult<Vec<LinkResponse>> {
+        let ids: Vec<Uuid> = links.iter().map(|l| l.id).collect();
+        let mut labels = self.repository.labels(&ids).await?;
+        Ok(links
+            .into_iter()
+            .map(|link| {
+                let labels = labels.remove(&link.id).unwrap_or_default();
+                self.link_to_response(link, labels)
+            })
+            .collect())
+    }
+
+    fn link_to_response(&self, link: Link, labels: LinkLabels) -> LinkResponse {
+        let redirect_type = self.effective_redirect_type(&link);
         LinkResponse {
-            key: link.key.clone(),
-            short_url: format!("{}/{}", self.base_url, link.key),
+            short_url: self.short_url(link.domain.as_deref(), &link.key),
+            qr_code_url: format!("{}/qr/{}", self.origin(link.domain.as_deref()), link.key),
+            key: link.key,
             original_url: link.original_url,
-            qr_code_url: format!("{}/qr/{}", self.base_url, link.key),
             created_at: link.created_at,
             expires_at: link.expires_at,
+            redirect_type,
+            folder: labels.folder,
+            tags: labels.tags,
+            password_protected: link.password_hash.is_some(),
+            max_clicks: link.max_clicks,
+            starts_at: link.starts_at,
+            prelaunch_url: link.prelaunch_url,
+            fallback_url: link.fallback_url,
+            forward_path: link.forward_path,
+            utm: (!link.utm.is_empty()).then_some(link.utm),
+            domain: link.domain,
+        }
+    }
+
+    /// The short URL of `key` on a custom domain, or on the default one.
+    pub fn short_url(&self, domain: Option<&str>, key: &str) -> String {
+        format!("{}/{}", self.origin(domain), key)
+    }
+
+    /// `BASE_URL`, or its scheme with a custom domain as the host.
+    fn origin(&self, domain: Option<&str>) -> String {
+        match domain {
+            Some(hostname) => {
+                let scheme = self.base_url.split_once("://").map_or("https", |(scheme, _)| scheme);
+                format!("{}://{}", scheme, hostname)
+            }
+            None => self.base_url.clone(),
         }
     }
 }
 
+/// Cuts `rows`, fetched with one row beyond `limit`, down to the page and returns its
+/// last row if another page follows.
+fn next_page<T>(rows: &mut Vec<T>, limit: i64) -> Option<&T> {
+    let limit = usize::try_from(limit).unwrap_or(0);
+    if rows.len() <= limit {
+        return None;
+    }
+    rows.truncate(limit);
+    rows.last()
+}
+
+/// Trims UTM values; blank ones count as unset.
+fn normalize_utm(utm: &Utm) -> Utm {
+    let normalize = |value: &Option<String>| {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+    };
+    Utm {
+        source: normalize(&utm.source),
+        medium: normalize(&utm.medium),
+        campaign: normalize(&utm.campaign),
+        term: normalize(&utm.term),
+        content: normalize(&utm.content),
+    }
+}
+
+/// Tags are case-insensitive: trimmed, lowercased, sorted and de-duplicated.
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags.iter().map(|t| t.trim().to_lowercase()).collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
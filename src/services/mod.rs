@@ -1,8 +1,26 @@
 pub mod link_service;
+pub mod domain_service;
+pub mod domain_verification;
+pub mod link_io;
+pub mod legacy_import;
+pub mod cursor;
+pub mod link_password;
+pub mod path_forwarding;
+pub mod utm_tagging;
+pub mod destination_template;
+pub mod geoip;
 pub mod qr_service;
 pub mod analytics_service;
+pub mod auth_service;
+pub mod error;
+pub mod usage_service;
 
 pub use link_service::LinkService;
+pub use domain_service::DomainService;
+pub use domain_verification::{NetworkProbe, VerificationProbe};
 pub use qr_service::QrService;
-pub use analytics_service::{AnalyticsService, AnalyticsData};
-
+pub use analytics_service::{AnalyticsService, AnalyticsData, ClickContext};
+pub use geoip::GeoIp;
+pub use auth_service::AuthService;
+pub use error::{ServiceError, ServiceResult};
+pub use usage_service::{UsageCharge, UsageService};
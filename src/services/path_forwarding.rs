@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use url::{form_urlencoded, Url};
+
+/// What follows the key in a visit to `/{key}/rest/of/path?x=1`: `rest/of/path` and
+/// `x=1`, both still percent-encoded the way the client sent them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedPath {
+    pub path: String,
+    pub query: Option<String>,
+}
+
+impl ForwardedPath {
+    /// Splits a redirect request's path, whose first segment is the key, and its query.
+    pub fn from_request(path: &str, query: Option<&str>) -> Self {
+        let rest = path
+            .trim_start_matches('/')
+            .split_once('/')
+            .map_or("", |(_, rest)| rest);
+        Self {
+            path: rest.to_string(),
+            query: query.filter(|q| !q.is_empty()).map(str::to_string),
+        }
+    }
+
+    pub fn has_path(&self) -> bool {
+        !self.path.is_empty()
+    }
+
+    /// The forwarded part as it appeared after the key, e.g. `/rest/of/path?x=1`.
+    pub fn suffix(&self) -> String {
+        let mut suffix = String::new();
+        if self.has_path() {
+            suffix.push('/');
+            suffix.push_str(&self.path);
+        }
+        if let Some(ref query) = self.query {
+            suffix.push('?');
+            suffix.push_str(query);
+        }
+        suffix
+    }
+}
+
+/// `destination` with `forwarded` carried over. The path goes below the destination's
+/// own path, whatever that ends in, and keeps its encoding. Query parameters are added
+/// after the destination's own, which win over forwarded ones of the same name; the
+/// destination's fragment stays at the end. `None` if the path would climb out of the
+/// destination's with dot segments.
+pub fn forward(destination: &str, forwarded: &ForwardedPath) -> Option<String> {
+    let mut url = Url::parse(destination).ok()?;
+
+    if forwarded.has_path() {
+        let base = url.path().trim_end_matches('/').to_string();
+        url.set_path(&format!("{}/{}", base, forwarded.path));
+        // `set_path` resolves `..`, including encoded ones, so check where it ended up.
+        if !url.path().starts_with(&format!("{}/", base)) {
+            return None;
+        }
+    }
+
+    if let Some(ref query) = forwarded.query {
+        let taken: HashSet<String> = url.query_pairs().map(|(name, _)| name.into_owned()).collect();
+        let mut pairs: Vec<&str> = Vec::new();
+        let own = url.query().unwrap_or("").to_string();
+        pairs.extend(own.split('&').filter(|pair| !pair.is_empty()));
+        pairs.extend(
+            query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .filter(|pair| !taken.contains(&parameter_name(pair))),
+        );
+        if !pairs.is_empty() {
+            url.set_query(Some(&pairs.join("&")));
+        }
+    }
+
+    Some(url.into())
+}
+
+fn parameter_name(pair: &str) -> String {
+    form_urlencoded::parse(pair.as_bytes())
+        .next()
+        .map(|(name, _)| name.into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(path: &str, query: Option<&str>) -> ForwardedPath {
+        ForwardedPath::from_request(path, query)
+    }
+
+    #[test]
+    fn test_forwarded_part_of_request() {
+        assert_eq!(forwarded("/docs", None), ForwardedPath::default());
+        assert_eq!(forwarded("/docs", Some("")), ForwardedPath::default());
+        let rest = forwarded("/docs/guide/intro%20page", Some("x=1"));
+        assert_eq!(rest.path, "guide/intro%20page");
+        assert_eq!(rest.suffix(), "/guide/intro%20page?x=1");
+        assert_eq!(forwarded("/docs/", None).suffix(), "");
+    }
+
+    #[test]
+    fn test_path_is_joined_below_destination() {
+        let rest = forwarded("/docs/guide/intro", None);
+        assert_eq!(forward("https://example.com", &rest).unwrap(), "https://example.com/guide/intro");
+        assert_eq!(forward("https://example.com/v2/", &rest).unwrap(), "https://example.com/v2/guide/intro");
+        assert_eq!(forward("https://example.com/v2", &rest).unwrap(), "https://example.com/v2/guide/intro");
+        assert_eq!(
+            forward("https://example.com/v2?lang=en#top", &forwarded("/docs/a%2Fb c", None)).unwrap(),
+            "https://example.com/v2/a%2Fb%20c?lang=en#top"
+        );
+    }
+
+    #[test]
+    fn test_dot_segments_cannot_leave_destination() {
+        for path in ["/docs/../admin", "/docs/a/../../admin", "/docs/%2e%2E/admin"] {
+            assert_eq!(forward("https://example.com/v2/", &forwarded(path, None)), None, "{path}");
+        }
+        assert_eq!(
+            forward("https://example.com/v2", &forwarded("/docs/a/../b", None)).unwrap(),
+            "https://example.com/v2/b"
+        );
+    }
+
+    #[test]
+    fn test_query_is_merged_without_overriding_destination() {
+        let rest = forwarded("/docs", Some("lang=fr&page=2&&q=a+b"));
+        assert_eq!(forward("https://example.com/", &rest).unwrap(), "https://example.com/?lang=fr&page=2&q=a+b");
+        assert_eq!(
+            forward("https://example.com/?lang=en&ref=x", &rest).unwrap(),
+            "https://example.com/?lang=en&ref=x&page=2&q=a+b"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use base64::{engine::general_purpose, Engine};
-use image::{ImageBuffer, ImageEncoder, Luma};
+use image::{ImageEncoder, Luma};
 use qrcode::QrCode;
 
 pub struct QrService;
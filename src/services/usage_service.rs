@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use std::future::Future;
+
+use crate::{
+    domain::{AuthContext, MetricUsage, QuotaUsage, UsageMetric, UsagePeriod, UsageReport},
+    repository::{UsageRepository, UsageWindow},
+    services::{ServiceError, ServiceResult},
+};
+
+/// Units taken by [`UsageService::consume`]. Remembers the day and month they were
+/// counted in, so a refund goes back to those windows even after they have rolled over.
+#[derive(Debug, Clone)]
+pub struct UsageCharge {
+    metric: UsageMetric,
+    windows: Vec<UsageWindow>,
+}
+
+#[derive(Clone)]
+pub struct UsageService {
+    repository: UsageRepository,
+}
+
+impl UsageService {
+    pub fn new(repository: UsageRepository) -> Self {
+        Self { repository }
+    }
+
+    /// Counts `amount` units of `metric` against the caller's daily and monthly quotas,
+    /// failing with `RateLimited` (retryable once the exhausted window resets) when either
+    /// would be exceeded.
+    pub async fn consume(
+        &self,
+        auth: &AuthContext,
+        metric: UsageMetric,
+        amount: i64,
+    ) -> ServiceResult<UsageCharge> {
+        let now = Utc::now();
+        let windows = Self::windows(auth, metric, now);
+
+        match self
+            .repository
+            .consume(auth.api_key_id, metric, amount, &windows)
+            .await?
+        {
+            None => Ok(UsageCharge { metric, windows }),
+            Some(period) => {
+                let resets_at = period.resets_at(now);
+                Err(ServiceError::RateLimited {
+                    message: format!(
+                        "{} quota for {} exhausted until {}",
+                        if period == UsagePeriod::Day {
+                            "Daily"
+                        } else {
+                            "Monthly"
+                        },
+                        metric.as_str(),
+                        resets_at.to_rfc3339()
+                    ),
+                    retry_after_secs: Some((resets_at - now).num_seconds().max(1) as u64),
+                })
+            }
+        }
+    }
+
+    /// Runs `op` as one metered unit of `metric`: the quota is charged up front and
+    /// refunded if the operation fails, so rejected requests are not billed.
+    pub async fn metered<T, F>(
+        &self,
+        auth: &AuthContext,
+        metric: UsageMetric,
+        op: F,
+    ) -> ServiceResult<T>
+    where
+        F: Future<Output = ServiceResult<T>>,
+    {
+        let charge = self.consume(auth, metric, 1).await?;
+
+        let result = op.await;
+        if result.is_err() {
+            self.refund(auth, &charge, 1).await;
+        }
+        result
+    }
+
+    /// Gives back `amount` units of `charge` for operations that subsequently failed.
+    pub async fn refund(&self, auth: &AuthContext, charge: &UsageCharge, amount: i64) {
+        if amount <= 0 {
+            return;
+        }
+
+        if let Err(e) = self
+            .repository
+            .refund(auth.api_key_id, charge.metric, amount, &charge.windows)
+            .await
+        {
+            tracing::warn!(
+                "Failed to refund {} usage for {}: {:?}",
+                charge.metric.as_str(),
+                auth.api_key_id,
+                e
+            );
+        }
+    }
+
+    pub async fn report(&self, auth: &AuthContext) -> ServiceResult<UsageReport> {
+        let now = Utc::now();
+        let counts = self
+            .repository
+            .get_counts(
+                auth.api_key_id,
+                UsagePeriod::Day.start(now),
+                UsagePeriod::Month.start(now),
+            )
+            .await?;
+
+        let used = |metric: UsageMetric, period: UsagePeriod| {
+            counts
+                .iter()
+                .find(|(m, p, _)| m == metric.as_str() && p == period.as_str())
+                .map_or(0, |(_, _, count)| *count)
+        };
+
+        let metrics = UsageMetric::ALL
+            .into_iter()
+            .map(|metric| MetricUsage {
+                metric: metric.as_str().to_string(),
+                daily: QuotaUsage {
+                    used: used(metric, UsagePeriod::Day),
+                    limit: auth.quotas.limit(metric, UsagePeriod::Day),
+                    resets_at: UsagePeriod::Day.resets_at(now),
+                },
+                monthly: QuotaUsage {
+                    used: used(metric, UsagePeriod::Month),
+                    limit: auth.quotas.limit(metric, UsagePeriod::Month),
+                    resets_at: UsagePeriod::Month.resets_at(now),
+                },
+            })
+            .collect();
+
+        Ok(UsageReport {
+            api_key_id: auth.api_key_id,
+            metrics,
+        })
+    }
+
+    fn windows(auth: &AuthContext, metric: UsageMetric, now: DateTime<Utc>) -> Vec<UsageWindow> {
+        UsagePeriod::ALL
+            .into_iter()
+            .map(|period| UsageWindow {
+                period,
+                start: period.start(now),
+                limit: auth.quotas.limit(metric, period),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_usage_period_boundaries() {
+        let now = Utc.with_ymd_and_hms(2024, 12, 31, 18, 30, 0).unwrap();
+
+        assert_eq!(UsagePeriod::Day.start(now).to_string(), "2024-12-31");
+        assert_eq!(UsagePeriod::Month.start(now).to_string(), "2024-12-01");
+        assert_eq!(
+            UsagePeriod::Day.resets_at(now),
+            Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            UsagePeriod::Month.resets_at(now),
+            Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use url::{form_urlencoded, Url};
+
+use crate::domain::Utm;
+
+/// `destination` with `utm` added to its query string. Parameters the destination
+/// already has are left alone, so hand-tagged URLs keep their own values. Destinations
+/// that gain nothing are returned exactly as they were.
+pub fn tag(destination: &str, utm: &Utm) -> String {
+    if utm.is_empty() {
+        return destination.to_string();
+    }
+    let Ok(mut url) = Url::parse(destination) else {
+        return destination.to_string();
+    };
+
+    let taken: HashSet<String> = url.query_pairs().map(|(name, _)| name.into_owned()).collect();
+    let added: Vec<(&str, &str)> = utm
+        .fields()
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .filter(|(name, _)| !taken.contains(*name))
+        .collect();
+    if added.is_empty() {
+        return destination.to_string();
+    }
+
+    let mut query = url.query().unwrap_or("").to_string();
+    for (name, value) in added {
+        if !query.is_empty() {
+            query.push('&');
+        }
+        query.push_str(name);
+        query.push('=');
+        query.extend(form_urlencoded::byte_serialize(value.as_bytes()));
+    }
+    url.set_query(Some(&query));
+
+    url.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utm(source: &str, campaign: &str) -> Utm {
+        Utm {
+            source: Some(source.to_string()),
+            medium: None,
+            campaign: Some(campaign.to_string()),
+            term: None,
+            content: None,
+        }
+    }
+
+    #[test]
+    fn test_utm_parameters_are_appended() {
+        assert_eq!(
+            tag("https://example.com/sale#top", &utm("newsletter", "spring sale")),
+            "https://example.com/sale?utm_source=newsletter&utm_campaign=spring+sale#top"
+        );
+        assert_eq!(tag("https://example.com/sale/", &Utm::default()), "https://example.com/sale/");
+    }
+
+    #[test]
+    fn test_existing_parameters_are_not_clobbered() {
+        assert_eq!(
+            tag("https://example.com/?id=7&utm_source=partner", &utm("newsletter", "spring")),
+            "https://example.com/?id=7&utm_source=partner&utm_campaign=spring"
+        );
+        assert_eq!(
+            tag("https://example.com/?utm_source=a&utm_campaign=b", &utm("newsletter", "spring")),
+            "https://example.com/?utm_source=a&utm_campaign=b"
+        );
+    }
+
+    #[test]
+    fn test_defaults_fill_unset_fields_only() {
+        let own = Utm { source: Some("x".to_string()), ..Default::default() };
+        let merged = own.or(&utm("newsletter", "spring"));
+        assert_eq!(merged, utm("x", "spring"));
+    }
+}